@@ -1,9 +1,140 @@
-use regex::Regex;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
 
 use std::cmp::Ordering;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The async_server module contains an artifact server which can be used from async code.
+#[cfg(feature = "async")]
+pub mod async_server;
+/// The cache module contains an on-disk cache of artifact server listings, so they can be
+/// shared between runs and used offline.
+pub mod cache;
+/// The changelog module contains how the changes between two builds are found.
+pub mod changelog;
+/// The source module contains the ways the list of builds can be read from what an artifact
+/// server responds with.
+pub mod source;
+
+#[cfg(feature = "async")]
+pub use async_server::AsyncArtifactServer;
+pub use cache::{Fetched, ListingCache};
+pub use changelog::{ArtifactDiff, BuildChange, ChangelogSource};
+pub use source::{ArtifactListing, ArtifactSource, HtmlListing, JsonChangelog};
+
+/// The URL of the artifact server for Linux builds.
+pub const LINUX_ARTIFACTS_URL: &str =
+    "https://runtime.fivem.net/artifacts/fivem/build_proot_linux/master/";
+/// The URL of the artifact server for Windows builds.
+pub const WINDOWS_ARTIFACTS_URL: &str =
+    "https://runtime.fivem.net/artifacts/fivem/build_server_windows/master/";
+
+/// The `User-Agent` sent with requests, which some APIs (such as GitHub's) require.
+pub const USER_AGENT: &str = concat!("fivem-utility/", env!("CARGO_PKG_VERSION"));
+
+/// The environment variable a GitHub token is read from. If it is set, the token is sent with
+/// requests to the GitHub API, which allows many more requests than it does anonymously.
+pub const GITHUB_TOKEN_VAR: &str = "GITHUB_TOKEN";
+
+/// The start of the URL of every GitHub API endpoint.
+const GITHUB_API_URL: &str = "https://api.github.com/";
+
+/// Creates a client for blocking requests.
+pub(crate) fn http_client() -> reqwest::blocking::Client {
+    reqwest::blocking::Client::builder()
+        .user_agent(USER_AGENT)
+        .build()
+        .unwrap()
+}
+
+/// Creates a client for async requests, which sends the same `User-Agent` as
+/// [`http_client`].
+#[cfg(feature = "async")]
+pub(crate) fn async_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .build()
+        .unwrap()
+}
+
+/// The platform a server build is for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    /// Linux builds, packaged as `fx.tar.xz`.
+    Linux,
+    /// Windows builds, packaged as `server.zip`.
+    Windows,
+}
+
+impl Platform {
+    /// The URL of the artifact server for builds for this platform.
+    pub fn url(&self) -> &'static str {
+        match self {
+            Platform::Linux => LINUX_ARTIFACTS_URL,
+            Platform::Windows => WINDOWS_ARTIFACTS_URL,
+        }
+    }
+}
+
+impl std::fmt::Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Platform::Linux => write!(f, "linux"),
+            Platform::Windows => write!(f, "windows"),
+        }
+    }
+}
+
+/// A channel of the artifact server, each pointing at one build.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtifactChannel {
+    /// The build recommended for servers to run.
+    Recommended,
+    /// The newest build considered stable enough to try, which may be newer than recommended.
+    Optional,
+    /// The newest build, whether or not it has been tested.
+    Latest,
+}
+
+impl ArtifactChannel {
+    /// Every channel, from the most to the least tested.
+    pub const ALL: [ArtifactChannel; 3] = [
+        ArtifactChannel::Recommended,
+        ArtifactChannel::Optional,
+        ArtifactChannel::Latest,
+    ];
+}
+
+impl std::str::FromStr for ArtifactChannel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "recommended" => Ok(ArtifactChannel::Recommended),
+            "optional" => Ok(ArtifactChannel::Optional),
+            "latest" => Ok(ArtifactChannel::Latest),
+            _ => Err(format!("unknown artifact channel `{}`", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for ArtifactChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArtifactChannel::Recommended => write!(f, "recommended"),
+            ArtifactChannel::Optional => write!(f, "optional"),
+            ArtifactChannel::Latest => write!(f, "latest"),
+        }
+    }
+}
 
 /// An artifact as can be found on the artifact server
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
 pub struct Artifact {
     /// The URL for the artifact folder.
     pub url: String,
@@ -11,6 +142,37 @@ pub struct Artifact {
     pub num: u16,
     /// The hash of the artifact.
     pub hash: String,
+    /// The file name of the archive containing the build, within the artifact folder.
+    /// This is `fx.tar.xz` for Linux builds and `server.zip` for Windows builds.
+    pub archive: String,
+    /// Whether the artifact server marks the build as broken or revoked.
+    pub broken: bool,
+}
+
+impl Artifact {
+    /// The URL to download the archive containing the build from.
+    pub fn download_url(&self) -> String {
+        format!("{}{}", self.url, self.archive)
+    }
+
+    /// The URLs the archive can be downloaded from, in the order to try them: its own, then the
+    /// same build on each of `mirrors`, which are laid out like the artifact server.
+    pub fn download_urls(&self, mirrors: &[String]) -> Vec<String> {
+        let mut urls = vec![self.download_url()];
+        for mirror in mirrors {
+            let url = format!(
+                "{}/{}-{}/{}",
+                mirror.trim_end_matches('/'),
+                self.num,
+                self.hash,
+                self.archive
+            );
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+        urls
+    }
 }
 
 impl Ord for Artifact {
@@ -21,106 +183,299 @@ impl Ord for Artifact {
 
 impl PartialOrd for Artifact {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.num.cmp(&other.num))
+        Some(self.cmp(other))
+    }
+}
+
+/// The error returned when the list of builds couldn't be read from the artifact server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArtifactError {
+    /// The artifact server couldn't be reached.
+    Fetch(String),
+    /// Offline mode is on, and the listing of the URL given isn't cached.
+    Offline(String),
+    /// None of the sources recognised what the artifact server responded with, which
+    /// probably means the artifact server has changed.
+    Unrecognised {
+        /// The names of the sources which were tried.
+        tried: Vec<&'static str>,
+    },
+    /// The URL given refused the request because too many have been made recently.
+    RateLimited(String),
+}
+
+impl std::fmt::Display for ArtifactError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArtifactError::Fetch(e) => write!(f, "failed to fetch artifact server content: {}", e),
+            ArtifactError::Offline(url) => {
+                write!(f, "offline mode is on and no listing of {} is cached", url)
+            }
+            ArtifactError::Unrecognised { tried } => write!(
+                f,
+                "the artifact server content wasn't recognised (tried {}); it may have changed",
+                tried.join(", ")
+            ),
+            ArtifactError::RateLimited(url) if url.starts_with(GITHUB_API_URL) => write!(
+                f,
+                "GitHub is rate limiting requests to {}; try again later, or set {} to a GitHub \
+                 token to be allowed more",
+                url, GITHUB_TOKEN_VAR
+            ),
+            ArtifactError::RateLimited(url) => {
+                write!(f, "{} is rate limiting requests; try again later", url)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArtifactError {}
+
+/// If a URL points at the local filesystem (`file://`), the path it points at.
+pub fn local_path(url: &str) -> Option<PathBuf> {
+    url.strip_prefix("file://").map(PathBuf::from)
+}
+
+/// The file a `file://` URL is read from: the file it points at, or the `index.html` inside it
+/// if it points at a directory, so a mirror written by [`mirror`](crate::mirror) can be used
+/// without a web server.
+pub(crate) fn local_file(url: &str) -> Option<PathBuf> {
+    let path = local_path(url)?;
+    Some(if path.is_dir() {
+        path.join("index.html")
+    } else {
+        path
+    })
+}
+
+/// The error reading a `file://` URL's file failed with.
+pub(crate) fn local_error(path: &Path, e: io::Error) -> ArtifactError {
+    ArtifactError::Fetch(format!("{}: {}", path.display(), e))
+}
+
+/// Fetches what is at a URL. A `file://` URL is read from [`local_file`].
+pub fn fetch_body(url: &str) -> Result<String, ArtifactError> {
+    if let Some(path) = local_file(url) {
+        return fs::read_to_string(&path).map_err(|e| local_error(&path, e));
+    }
+    let response = request(url)
+        .send()
+        .map_err(|e| ArtifactError::Fetch(e.to_string()))?;
+    check_status(url, response)?
+        .text()
+        .map_err(|e| ArtifactError::Fetch(e.to_string()))
+}
+
+/// The GitHub token to send with a request to a URL: the token in [`GITHUB_TOKEN_VAR`], if it
+/// is set and the URL is of the GitHub API.
+pub(crate) fn github_token(url: &str) -> Option<String> {
+    env::var(GITHUB_TOKEN_VAR)
+        .ok()
+        .filter(|token| !token.is_empty() && url.starts_with(GITHUB_API_URL))
+}
+
+/// Starts a GET request to a URL, sending the token from [`github_token`] if there is one.
+pub(crate) fn request(url: &str) -> reqwest::blocking::RequestBuilder {
+    let request = http_client().get(url);
+    match github_token(url) {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    }
+}
+
+/// Whether a response from a URL with `status` means too many requests have been made. GitHub
+/// refuses requests with `403 Forbidden` once too many have been made, so that is reported as
+/// rate limiting along with `429 Too Many Requests`.
+pub(crate) fn rate_limited(url: &str, status: StatusCode) -> Option<ArtifactError> {
+    match status {
+        StatusCode::TOO_MANY_REQUESTS => Some(ArtifactError::RateLimited(url.to_string())),
+        StatusCode::FORBIDDEN if url.starts_with(GITHUB_API_URL) => {
+            Some(ArtifactError::RateLimited(url.to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// Checks a response was successful, reporting rate limiting as [`rate_limited`] does.
+pub(crate) fn check_status(
+    url: &str,
+    response: reqwest::blocking::Response,
+) -> Result<reqwest::blocking::Response, ArtifactError> {
+    match rate_limited(url, response.status()) {
+        Some(e) => Err(e),
+        None => response
+            .error_for_status()
+            .map_err(|e| ArtifactError::Fetch(e.to_string())),
+    }
+}
+
+/// The artifact servers a listing is read from, tried in turn until one can be read. This is
+/// shared by [`ArtifactServer`] and `AsyncArtifactServer`, which only differ in how they fetch.
+#[derive(Clone, Debug)]
+pub(crate) struct Mirrors<'a> {
+    urls: Vec<&'a str>,
+    /// The artifact server being tried, or the one the listing was read from once it has been.
+    current: usize,
+    sources: Vec<&'a dyn ArtifactSource>,
+    listing: Option<Result<ArtifactListing, ArtifactError>>,
+    warnings: Vec<String>,
+}
+
+impl<'a> Mirrors<'a> {
+    pub(crate) fn new(url: &'a str, sources: Vec<&'a dyn ArtifactSource>) -> Self {
+        Self {
+            urls: vec![url],
+            current: 0,
+            sources,
+            listing: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, url: &'a str) {
+        self.urls.push(url);
+    }
+
+    pub(crate) fn url(&self) -> &'a str {
+        self.urls[self.current]
+    }
+
+    /// The URL to fetch the listing from next, or `None` once it has been read or every
+    /// artifact server has failed.
+    pub(crate) fn pending(&self) -> Option<&'a str> {
+        self.listing.is_none().then(|| self.url())
+    }
+
+    /// Records what was fetched from the [`pending`](Self::pending) URL. If it couldn't be read
+    /// and there is another mirror, why is kept as a warning and the next mirror is tried.
+    pub(crate) fn record(&mut self, fetched: Result<Fetched, ArtifactError>) {
+        let url = self.url();
+        let listing = fetched.and_then(|fetched| {
+            self.warnings.extend(fetched.warning);
+            source::parse_listing(&self.sources, url, &fetched.body)
+        });
+        match listing {
+            Err(e) if self.current + 1 < self.urls.len() => {
+                self.warnings.push(format!("{}: {}", url, e));
+                self.current += 1;
+            }
+            listing => self.listing = Some(listing),
+        }
+    }
+
+    /// The listing, once [`pending`](Self::pending) has returned `None`.
+    pub(crate) fn listing(&self) -> Result<&ArtifactListing, ArtifactError> {
+        self.listing
+            .as_ref()
+            .unwrap()
+            .as_ref()
+            .map_err(|e| e.clone())
+    }
+
+    pub(crate) fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
     }
 }
 
 /// An instance of an artifact server. This caches responses to provide the quickest response it can each time.
+/// If mirrors are added, each is tried in turn until one can be read.
 #[derive(Clone, Debug)]
 pub struct ArtifactServer<'a> {
-    url: &'a str,
-    body: Option<String>,
-    latest: Option<u16>,
-    artifacts: Option<Vec<Artifact>>,
+    mirrors: Mirrors<'a>,
+    cache: Option<ListingCache>,
 }
 
 impl<'a> ArtifactServer<'a> {
-    /// Create a new Artifact server with caching
+    /// Create a new Artifact server with caching, which understands both the HTML listing and
+    /// the JSON changelog API.
     pub fn new(url: &'a str) -> Self {
+        Self::with_sources(url, vec![&JsonChangelog, &HtmlListing])
+    }
+
+    /// Create a new Artifact server with caching, reading what it responds with using the first
+    /// of `sources` which recognises it.
+    pub fn with_sources(url: &'a str, sources: Vec<&'a dyn ArtifactSource>) -> Self {
         Self {
-            url: url,
-            body: None,
-            artifacts: None,
-            latest: None,
+            mirrors: Mirrors::new(url, sources),
+            cache: None,
         }
     }
 
-    fn get_body(&mut self) -> bool {
-        if let Ok(body) = reqwest::blocking::get(self.url) {
-            if let Ok(body) = body.text() {
-                self.body = Some(body);
-                return true;
-            }
+    /// Adds a mirror, which is tried if the artifact servers before it can't be read.
+    pub fn with_mirror(mut self, url: &'a str) -> Self {
+        self.mirrors.push(url);
+        self
+    }
+
+    /// The URL of the artifact server the listing was read from, or the first one if it hasn't
+    /// been read yet.
+    pub fn url(&self) -> &'a str {
+        self.mirrors.url()
+    }
+
+    /// Caches what the artifact server responds with on disk, so it can be shared with later
+    /// runs.
+    pub fn with_cache(mut self, cache: ListingCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    fn get_body(&self, url: &str) -> Result<Fetched, ArtifactError> {
+        match &self.cache {
+            Some(cache) if local_path(url).is_none() => cache.fetch(url),
+            _ => fetch_body(url).map(|body| Fetched {
+                body,
+                warning: None,
+            }),
+        }
+    }
+
+    /// Takes the warnings about reading the artifact server given since they were last taken,
+    /// such as mirrors which couldn't be read before one which could, or a cached listing being
+    /// used because the artifact server couldn't be reached.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        self.mirrors.take_warnings()
+    }
+
+    /// Gets everything known about the builds on the artifact server, or why it couldn't be
+    /// read. If there are mirrors, the first which can be read is used, and why each one before
+    /// it couldn't be read is kept as a warning.
+    pub fn listing(&mut self) -> Result<&ArtifactListing, ArtifactError> {
+        while let Some(url) = self.mirrors.pending() {
+            let fetched = self.get_body(url);
+            self.mirrors.record(fetched);
         }
-        eprintln!("!! Failed to fetch artifact server content !!");
-        false
+        self.mirrors.listing()
     }
 
     /// Gets a list of artifacts found on the artifact server.
-    /// If the artifact server is invalid (or is updated and this crate
-    /// is not), an empty list will always be returned.
+    /// If the artifact server can't be read, an empty list is returned; use
+    /// [`listing`](Self::listing) to find out why.
     pub fn get_artifacts(&mut self) -> Vec<Artifact> {
-        if let Some(arts) = &self.artifacts {
-            return arts.clone();
-        }
-        if self.body == None {
-            self.get_body();
-        }
-        if let Some(body) = &self.body {
-            let re = Regex::new(r"(\d+)\-([\da-f]+)/fx\.tar\.xz").unwrap();
-            let mut versions: Vec<Artifact> = Vec::new();
-            let mut nums_already: Vec<u16> = Vec::new();
-
-            for capture in re.captures_iter(&body) {
-                let num = (&capture[1]).parse::<u16>().unwrap();
-                if !nums_already.contains(&num) {
-                    nums_already.push(num);
-                    versions.push(Artifact {
-                        url: format!("{}{}-{}/", self.url, &capture[1], &capture[2]),
-                        num: num,
-                        hash: (&capture[2]).to_string(),
-                    });
-                }
-            }
-            self.artifacts = Some(versions);
-            return self.artifacts.clone().unwrap();
-        }
-        vec![]
+        self.listing()
+            .map(|listing| listing.artifacts.clone())
+            .unwrap_or_default()
     }
 
     /// Gets the number of the latest recommended artifact.
-    /// If the artifact server is invalid (or is updated and this crate
-    /// is not), zero (`0u16`) will be returned
+    /// If the artifact server can't be read, zero (`0u16`) will be returned.
     pub fn get_latest_version_num(&mut self) -> u16 {
-        if let Some(latest) = self.latest {
-            return latest;
-        }
-        if self.body == None {
-            self.get_body();
-        }
-        if let Some(body) = &self.body {
-            let re = Regex::new(r"RECOMMENDED \((\d+)\)").unwrap();
+        self.channel_num(ArtifactChannel::Recommended).unwrap_or(0)
+    }
 
-            for capture in re.captures_iter(&body) {
-                let num = (&capture[1]).parse::<u16>().unwrap();
-                self.latest = Some(num);
-                return num;
-            }
-        }
-        0u16
+    /// Gets the number of the artifact a channel points at, if the artifact server lists one.
+    /// `Latest` is the newest build listed, unless the artifact server names one.
+    pub fn channel_num(&mut self, channel: ArtifactChannel) -> Option<u16> {
+        self.listing().ok()?.channel_num(channel)
+    }
+
+    /// Gets the artifact a channel points at, if the artifact server lists one.
+    pub fn channel(&mut self, channel: ArtifactChannel) -> Option<Artifact> {
+        self.listing().ok()?.channel(channel).cloned()
     }
 
     /// Linear search for an artifact
     pub fn get_artifact(&mut self, num: u16) -> Option<Artifact> {
-        if self.artifacts == None {
-            self.get_artifacts();
-        }
-
-        for ar in self.artifacts.as_ref().unwrap_or(&Vec::new()) {
-            if ar.num == num {
-                return Some(ar.clone());
-            }
-        }
-        None
+        self.listing().ok()?.artifact(num).cloned()
     }
 }
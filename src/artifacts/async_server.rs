@@ -0,0 +1,238 @@
+use super::source::{ArtifactListing, ArtifactSource, HtmlListing, JsonChangelog};
+use super::{
+    async_client, github_token, local_error, local_file, rate_limited, Artifact, ArtifactChannel,
+    ArtifactError, Fetched, ListingCache, Mirrors,
+};
+
+/// An instance of an artifact server which can be used from async code, such as a bot running
+/// on tokio. This offers the same operations as [`ArtifactServer`](super::ArtifactServer),
+/// including mirrors, `file://` URLs and the on-disk [`ListingCache`], and caches responses in
+/// memory in the same way.
+#[derive(Clone, Debug)]
+pub struct AsyncArtifactServer<'a> {
+    mirrors: Mirrors<'a>,
+    cache: Option<ListingCache>,
+    client: reqwest::Client,
+}
+
+impl<'a> AsyncArtifactServer<'a> {
+    /// Create a new Artifact server with caching, which understands both the HTML listing and
+    /// the JSON changelog API.
+    pub fn new(url: &'a str) -> Self {
+        Self::with_sources(url, vec![&JsonChangelog, &HtmlListing])
+    }
+
+    /// Create a new Artifact server with caching, reading what it responds with using the first
+    /// of `sources` which recognises it.
+    pub fn with_sources(url: &'a str, sources: Vec<&'a dyn ArtifactSource>) -> Self {
+        Self {
+            mirrors: Mirrors::new(url, sources),
+            cache: None,
+            client: async_client(),
+        }
+    }
+
+    /// Adds a mirror, which is tried if the artifact servers before it can't be read.
+    pub fn with_mirror(mut self, url: &'a str) -> Self {
+        self.mirrors.push(url);
+        self
+    }
+
+    /// The URL of the artifact server the listing was read from, or the first one if it hasn't
+    /// been read yet.
+    pub fn url(&self) -> &'a str {
+        self.mirrors.url()
+    }
+
+    /// Caches what the artifact server responds with on disk, so it can be shared with later
+    /// runs and with [`ArtifactServer`](super::ArtifactServer).
+    pub fn with_cache(mut self, cache: ListingCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    async fn get_body(&self, url: &str) -> Result<Fetched, ArtifactError> {
+        if let Some(path) = local_file(url) {
+            return tokio::fs::read_to_string(&path)
+                .await
+                .map(|body| Fetched {
+                    body,
+                    warning: None,
+                })
+                .map_err(|e| local_error(&path, e));
+        }
+        if let Some(cache) = &self.cache {
+            // The cache reads and writes files and revalidates with blocking requests
+            let (cache, url) = (cache.clone(), url.to_string());
+            return tokio::task::spawn_blocking(move || cache.fetch(&url))
+                .await
+                .map_err(|e| ArtifactError::Fetch(e.to_string()))?;
+        }
+        let mut request = self.client.get(url);
+        if let Some(token) = github_token(url) {
+            request = request.bearer_auth(token);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| ArtifactError::Fetch(e.to_string()))?;
+        if let Some(e) = rate_limited(url, response.status()) {
+            return Err(e);
+        }
+        let fetch = async { response.error_for_status()?.text().await };
+        fetch
+            .await
+            .map(|body| Fetched {
+                body,
+                warning: None,
+            })
+            .map_err(|e| ArtifactError::Fetch(e.to_string()))
+    }
+
+    /// Takes the warnings about reading the artifact server given since they were last taken,
+    /// such as mirrors which couldn't be read before one which could, or a cached listing being
+    /// used because the artifact server couldn't be reached.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        self.mirrors.take_warnings()
+    }
+
+    /// Gets everything known about the builds on the artifact server, or why it couldn't be
+    /// read. If there are mirrors, the first which can be read is used, and why each one before
+    /// it couldn't be read is kept as a warning.
+    pub async fn listing(&mut self) -> Result<&ArtifactListing, ArtifactError> {
+        while let Some(url) = self.mirrors.pending() {
+            let fetched = self.get_body(url).await;
+            self.mirrors.record(fetched);
+        }
+        self.mirrors.listing()
+    }
+
+    /// Gets a list of artifacts found on the artifact server.
+    /// If the artifact server can't be read, an empty list is returned; use
+    /// [`listing`](Self::listing) to find out why.
+    pub async fn get_artifacts(&mut self) -> Vec<Artifact> {
+        self.listing()
+            .await
+            .map(|listing| listing.artifacts.clone())
+            .unwrap_or_default()
+    }
+
+    /// Gets the number of the latest recommended artifact.
+    /// If the artifact server can't be read, zero (`0u16`) will be returned.
+    pub async fn get_latest_version_num(&mut self) -> u16 {
+        self.channel_num(ArtifactChannel::Recommended)
+            .await
+            .unwrap_or(0)
+    }
+
+    /// Gets the number of the artifact a channel points at, if the artifact server lists one.
+    /// `Latest` is the newest build listed, unless the artifact server names one.
+    pub async fn channel_num(&mut self, channel: ArtifactChannel) -> Option<u16> {
+        self.listing().await.ok()?.channel_num(channel)
+    }
+
+    /// Gets the artifact a channel points at, if the artifact server lists one.
+    pub async fn channel(&mut self, channel: ArtifactChannel) -> Option<Artifact> {
+        self.listing().await.ok()?.channel(channel).cloned()
+    }
+
+    /// Linear search for an artifact
+    pub async fn get_artifact(&mut self, num: u16) -> Option<Artifact> {
+        self.listing().await.ok()?.artifact(num).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifacts::cache::CacheEntry;
+    use crate::test_util::{serve, temp_dir};
+    use std::fs;
+    use std::time::Duration;
+
+    #[test]
+    fn reads_the_listing() {
+        let body = include_str!("../../tests/fixtures/artifacts/linux.html");
+        let url = serve(vec![("200 OK", body.as_bytes().to_vec())]);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let mut server = AsyncArtifactServer::new(&url);
+            assert_eq!(server.get_latest_version_num().await, 5848);
+            assert_eq!(
+                server.channel(ArtifactChannel::Latest).await.unwrap().num,
+                6116
+            );
+            assert!(server.get_artifact(6097).await.unwrap().broken);
+            assert_eq!(server.get_artifacts().await.len(), 6);
+        });
+    }
+
+    #[test]
+    fn falls_back_across_mirrors() {
+        let dir = temp_dir("async-mirror");
+        fs::write(
+            dir.join("index.html"),
+            include_str!("../../tests/fixtures/artifacts/linux.html"),
+        )
+        .unwrap();
+        let limited = serve(vec![("429 Too Many Requests", Vec::new())]);
+        let mirror = format!("file://{}/", dir.display());
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let mut server = AsyncArtifactServer::new(&limited).with_mirror(&mirror);
+            assert_eq!(server.get_latest_version_num().await, 5848);
+            assert_eq!(server.url(), mirror);
+            assert_eq!(
+                server.take_warnings(),
+                vec![format!(
+                    "{}: {}",
+                    limited,
+                    ArtifactError::RateLimited(limited.clone())
+                )]
+            );
+        });
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_through_the_cache() {
+        // Nothing listens on the discard port, so the listing can only come from the cache
+        let url = "http://127.0.0.1:9/artifacts/";
+        let cache = ListingCache {
+            dir: temp_dir("async-cache"),
+            ttl: Duration::from_secs(60),
+            offline: true,
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let mut server = AsyncArtifactServer::new(url).with_cache(cache.clone());
+            assert_eq!(
+                server.listing().await,
+                Err(ArtifactError::Offline(url.to_string()))
+            );
+
+            cache.store(&CacheEntry {
+                url: url.to_string(),
+                fetched_at: 0,
+                etag: None,
+                last_modified: None,
+                body: include_str!("../../tests/fixtures/artifacts/linux.html").to_string(),
+            });
+            let mut server = AsyncArtifactServer::new(url).with_cache(cache.clone());
+            assert_eq!(server.get_latest_version_num().await, 5848);
+        });
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+}
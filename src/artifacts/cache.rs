@@ -0,0 +1,308 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::ArtifactError;
+
+/// A listing fetched from an artifact server, as stored in the cache.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// The URL the listing was fetched from.
+    pub url: String,
+    /// When the listing was last fetched or revalidated, in seconds since the Unix epoch.
+    pub fetched_at: u64,
+    /// The `ETag` the artifact server gave the listing, if any.
+    pub etag: Option<String>,
+    /// The `Last-Modified` time the artifact server gave the listing, if any.
+    pub last_modified: Option<String>,
+    /// The listing.
+    pub body: String,
+}
+
+/// A listing read by [`ListingCache::fetch`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fetched {
+    /// The listing.
+    pub body: String,
+    /// Why a cached listing was used instead of the artifact server's, if it had to be.
+    pub warning: Option<String>,
+}
+
+/// An on-disk cache of artifact server listings, shared between runs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListingCache {
+    /// The directory cached listings are stored in.
+    pub dir: PathBuf,
+    /// How long a cached listing is used for before it is revalidated with the artifact server.
+    pub ttl: Duration,
+    /// Whether to only ever use cached listings, never contacting the artifact server.
+    pub offline: bool,
+}
+
+/// The directory listings are cached in by default: `$XDG_CACHE_HOME/fivem-utility`, or
+/// `~/.cache/fivem-utility` if `XDG_CACHE_HOME` isn't set.
+pub fn default_dir() -> PathBuf {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME")
+                .or_else(|| env::var_os("USERPROFILE"))
+                .map(|home| PathBuf::from(home).join(".cache"))
+        })
+        .unwrap_or_else(env::temp_dir);
+    base.join("fivem-utility")
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl ListingCache {
+    /// Creates a cache in the default directory.
+    pub fn new(ttl: Duration, offline: bool) -> Self {
+        Self {
+            dir: default_dir(),
+            ttl,
+            offline,
+        }
+    }
+
+    /// The file a URL's listing is cached in.
+    fn path(&self, url: &str) -> PathBuf {
+        let digest = Sha256::digest(url.as_bytes());
+        let name: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+        self.dir.join(format!("listing-{}.json", name))
+    }
+
+    /// Reads the cached listing of a URL, if there is one.
+    pub fn load(&self, url: &str) -> Option<CacheEntry> {
+        let text = fs::read_to_string(self.path(url)).ok()?;
+        serde_json::from_str::<CacheEntry>(&text)
+            .ok()
+            .filter(|entry| entry.url == url)
+    }
+
+    /// Stores a listing in the cache. Failing to write the cache isn't an error, as it only
+    /// makes later runs slower.
+    pub fn store(&self, entry: &CacheEntry) {
+        if fs::create_dir_all(&self.dir).is_ok() {
+            let _ = fs::write(self.path(&entry.url), serde_json::to_string(entry).unwrap());
+        }
+    }
+
+    /// Gets the listing of a URL, from the cache while it is fresh. Once it is older than the
+    /// TTL it is revalidated with the artifact server using `If-None-Match` and
+    /// `If-Modified-Since`. If the artifact server can't be reached or fails with a server
+    /// error, a stale listing is used and a warning saying so is returned with it.
+    pub fn fetch(&self, url: &str) -> Result<Fetched, ArtifactError> {
+        let fetched = |body| Fetched {
+            body,
+            warning: None,
+        };
+        let stale = |entry: CacheEntry, reason: String| Fetched {
+            body: entry.body,
+            warning: Some(format!(
+                "failed to reach {} ({}), so a cached listing is used",
+                url, reason
+            )),
+        };
+        let cached = self.load(url);
+        if self.offline {
+            return cached
+                .map(|entry| fetched(entry.body))
+                .ok_or_else(|| ArtifactError::Offline(url.to_string()));
+        }
+        if let Some(entry) = &cached {
+            if now().saturating_sub(entry.fetched_at) < self.ttl.as_secs() {
+                return Ok(fetched(entry.body.clone()));
+            }
+        }
+
+        let mut request = super::request(url);
+        if let Some(entry) = &cached {
+            if let Some(etag) = &entry.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &entry.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let response = match request.send() {
+            Ok(response) => response,
+            Err(e) => {
+                return match cached {
+                    Some(entry) => Ok(stale(entry, e.to_string())),
+                    None => Err(ArtifactError::Fetch(e.to_string())),
+                };
+            }
+        };
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(mut entry) = cached {
+                entry.fetched_at = now();
+                self.store(&entry);
+                return Ok(fetched(entry.body));
+            }
+        }
+        if response.status().is_server_error() {
+            if let Some(entry) = cached {
+                return Ok(stale(entry, response.status().to_string()));
+            }
+        }
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok())
+                .map(String::from)
+        };
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        let body = super::check_status(url, response)?
+            .text()
+            .map_err(|e| ArtifactError::Fetch(e.to_string()))?;
+        self.store(&CacheEntry {
+            url: url.to_string(),
+            fetched_at: now(),
+            etag,
+            last_modified,
+            body: body.clone(),
+        });
+        Ok(fetched(body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    // Nothing listens on the discard port, so any request made fails
+    const URL: &str = "http://127.0.0.1:9/artifacts/";
+
+    fn cache(name: &str, ttl: u64, offline: bool) -> ListingCache {
+        ListingCache {
+            dir: temp_dir(&format!("cache-{}", name)),
+            ttl: Duration::from_secs(ttl),
+            offline,
+        }
+    }
+
+    fn entry(fetched_at: u64) -> CacheEntry {
+        CacheEntry {
+            url: URL.to_string(),
+            fetched_at,
+            etag: Some("\"abc\"".to_string()),
+            last_modified: None,
+            body: "cached".to_string(),
+        }
+    }
+
+    #[test]
+    fn fresh_listings_are_served_from_the_cache() {
+        let cache = cache("fresh", 60, false);
+        let fresh = entry(now());
+        cache.store(&fresh);
+        assert_eq!(cache.load(URL), Some(fresh));
+        assert_eq!(cache.fetch(URL).unwrap().body, "cached");
+        assert_eq!(cache.load("http://127.0.0.1:9/other/"), None);
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn stale_listings_are_used_when_the_server_is_unreachable() {
+        let cache = cache("stale", 60, false);
+        assert!(matches!(cache.fetch(URL), Err(ArtifactError::Fetch(_))));
+        cache.store(&entry(0));
+        let fetched = cache.fetch(URL).unwrap();
+        assert_eq!(fetched.body, "cached");
+        assert!(fetched.warning.unwrap().starts_with("failed to reach "));
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn stale_listings_are_used_when_the_server_fails() {
+        let cache = cache("failing", 60, false);
+        let url = crate::test_util::serve(vec![
+            ("503 Service Unavailable", vec![]),
+            ("503 Service Unavailable", vec![]),
+        ]);
+        assert!(matches!(cache.fetch(&url), Err(ArtifactError::Fetch(_))));
+        cache.store(&CacheEntry {
+            url: url.clone(),
+            ..entry(0)
+        });
+        let fetched = cache.fetch(&url).unwrap();
+        assert_eq!(fetched.body, "cached");
+        assert!(fetched
+            .warning
+            .unwrap()
+            .ends_with("(503 Service Unavailable), so a cached listing is used"));
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn stale_listings_are_revalidated() {
+        // Only answers "not modified" when asked with the cached listing's ETag
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            let mut request = [0; 4096];
+            let read = stream.read(&mut request).unwrap();
+            let request = String::from_utf8_lossy(&request[..read]).to_lowercase();
+            let response = if request.contains("if-none-match: \"abc\"\r\n") {
+                "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n"
+            } else {
+                "HTTP/1.1 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\nnew"
+            };
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+
+        let cache = cache("revalidate", 60, false);
+        cache.store(&CacheEntry {
+            url: url.clone(),
+            ..entry(0)
+        });
+        assert_eq!(
+            cache.fetch(&url),
+            Ok(Fetched {
+                body: "cached".to_string(),
+                warning: None,
+            })
+        );
+        assert!(cache.load(&url).unwrap().fetched_at > 0);
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn offline_only_uses_the_cache() {
+        let cache = cache("offline", 0, true);
+        assert_eq!(
+            cache.fetch(URL),
+            Err(ArtifactError::Offline(URL.to_string()))
+        );
+        cache.store(&entry(0));
+        assert_eq!(
+            cache.fetch(URL),
+            Ok(Fetched {
+                body: "cached".to_string(),
+                warning: None,
+            })
+        );
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+}
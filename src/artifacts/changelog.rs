@@ -0,0 +1,249 @@
+use serde::Serialize;
+
+use super::{fetch_body, local_path, Artifact, ArtifactError, ListingCache};
+
+/// Where the summary of a build's changes is read from by default: the GitHub commits API for
+/// the FiveM repository, as every build is named after the commit it was built from. Without a
+/// token in [`GITHUB_TOKEN_VAR`](super::GITHUB_TOKEN_VAR), GitHub only allows a few requests an
+/// hour.
+pub const DEFAULT_CHANGELOG_URL: &str =
+    "https://api.github.com/repos/citizenfx/fivem/commits/{hash}";
+
+/// Where the summary of a build's changes is read from. The URL is a template, with `{hash}`
+/// standing for the build's commit hash. A response from the GitHub commits API is understood,
+/// otherwise the first line of the response is used as the summary.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChangelogSource {
+    /// The URL template.
+    pub url: String,
+    /// The cache summaries are kept in, if any. Commits don't change, so this saves asking
+    /// again on every run.
+    pub cache: Option<ListingCache>,
+}
+
+impl Default for ChangelogSource {
+    fn default() -> Self {
+        Self::new(DEFAULT_CHANGELOG_URL)
+    }
+}
+
+impl ChangelogSource {
+    /// Creates a source reading summaries from a URL template.
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            cache: None,
+        }
+    }
+
+    /// Keeps summaries in a cache.
+    pub fn with_cache(mut self, cache: ListingCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// The URL the summary of a commit is read from.
+    pub fn url_for(&self, hash: &str) -> String {
+        self.url.replace("{hash}", hash)
+    }
+
+    /// Reads the summary of a commit.
+    pub fn summary(&self, hash: &str) -> Result<String, ArtifactError> {
+        let url = self.url_for(hash);
+        let body = match &self.cache {
+            Some(cache) if local_path(&url).is_none() => cache.fetch(&url)?.body,
+            _ => fetch_body(&url)?,
+        };
+        parse_summary(&body)
+            .ok_or_else(|| ArtifactError::Fetch(format!("{}: no summary found", url)))
+    }
+}
+
+/// Reads a commit summary from a response: the first line of the commit message from the
+/// GitHub commits API, or the first line of anything else.
+pub fn parse_summary(body: &str) -> Option<String> {
+    let text = match serde_json::from_str::<serde_json::Value>(body) {
+        Ok(json) => json
+            .pointer("/commit/message")
+            .or_else(|| json.get("message"))?
+            .as_str()?
+            .to_string(),
+        Err(_) => body.to_string(),
+    };
+    text.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(String::from)
+}
+
+/// A build between the two builds being compared.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct BuildChange {
+    /// The artifact number of the build.
+    pub num: u16,
+    /// The commit hash of the build.
+    pub hash: String,
+    /// Whether the build has been marked as broken.
+    pub broken: bool,
+    /// The summary of the build's changes, if it could be read.
+    pub summary: Option<String>,
+}
+
+/// The builds between two builds, as found by [`ArtifactDiff::between`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ArtifactDiff {
+    /// The artifact number of the build being moved from.
+    pub from: u16,
+    /// The artifact number of the build being moved to.
+    pub to: u16,
+    /// How many builds would be skipped by moving straight from one to the other.
+    pub skipped: usize,
+    /// The builds after the older build, up to and including the newer one, oldest first.
+    pub builds: Vec<BuildChange>,
+}
+
+impl ArtifactDiff {
+    /// Finds the builds in `artifacts` between the builds numbered `from` and `to`, which can
+    /// be given either way round. Summaries aren't read until
+    /// [`fetch_summaries`](Self::fetch_summaries) is called.
+    pub fn between(artifacts: &[Artifact], from: u16, to: u16) -> Self {
+        let (oldest, newest) = (from.min(to), from.max(to));
+        let mut builds: Vec<&Artifact> = artifacts
+            .iter()
+            .filter(|a| a.num > oldest && a.num <= newest)
+            .collect();
+        builds.sort();
+        builds.dedup_by_key(|a| a.num);
+        Self {
+            from,
+            to,
+            skipped: builds.iter().filter(|a| a.num < newest).count(),
+            builds: builds
+                .into_iter()
+                .map(|a| BuildChange {
+                    num: a.num,
+                    hash: a.hash.clone(),
+                    broken: a.broken,
+                    summary: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Reads the summary of every build from `source`. Builds whose summary can't be read are
+    /// left without one, and the last error is returned. Once `source` refuses a request for
+    /// being rate limited no more are made, as the rest would be refused too.
+    pub fn fetch_summaries(&mut self, source: &ChangelogSource) -> Result<(), ArtifactError> {
+        let mut result = Ok(());
+        for build in &mut self.builds {
+            match source.summary(&build.hash) {
+                Ok(summary) => build.summary = Some(summary),
+                Err(e @ ArtifactError::RateLimited(_)) => return Err(e),
+                Err(e) => result = Err(e),
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use std::fs;
+
+    fn artifact(num: u16, hash: &str) -> Artifact {
+        Artifact {
+            url: String::new(),
+            num,
+            hash: hash.to_string(),
+            archive: "fx.tar.xz".to_string(),
+            broken: num == 5900,
+        }
+    }
+
+    #[test]
+    fn lists_the_builds_between_two_builds() {
+        let dir = temp_dir("changelog");
+        fs::write(
+            dir.join("aaa"),
+            r#"{"sha": "aaa", "commit": {"message": "tweak(server): faster\n\nDetails"}}"#,
+        )
+        .unwrap();
+        fs::write(dir.join("bbb"), "\nfix: broken build\n").unwrap();
+
+        let artifacts = vec![
+            artifact(5848, "zzz"),
+            artifact(6116, "bbb"),
+            artifact(5900, "aaa"),
+            artifact(6200, "ccc"),
+        ];
+        let mut diff = ArtifactDiff::between(&artifacts, 6116, 5848);
+        assert_eq!(diff.skipped, 1);
+        assert_eq!(
+            diff.builds.iter().map(|b| b.num).collect::<Vec<_>>(),
+            vec![5900, 6116]
+        );
+        assert!(diff.builds[0].broken);
+
+        let source = ChangelogSource::new(&format!("file://{}/{{hash}}", dir.display()));
+        assert!(diff.fetch_summaries(&source).is_ok());
+        assert_eq!(
+            diff.builds[0].summary.as_deref(),
+            Some("tweak(server): faster")
+        );
+        assert_eq!(diff.builds[1].summary.as_deref(), Some("fix: broken build"));
+
+        diff.builds.push(BuildChange {
+            num: 6200,
+            hash: "ccc".to_string(),
+            broken: false,
+            summary: None,
+        });
+        assert!(diff.fetch_summaries(&source).is_err());
+        assert_eq!(diff.builds[2].summary, None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rate_limiting_is_explained() {
+        let url = crate::test_util::serve(vec![
+            ("429 Too Many Requests", Vec::new()),
+            ("403 Forbidden", Vec::new()),
+        ]);
+        let source = ChangelogSource::new(&format!("{}{{hash}}", url));
+        assert_eq!(
+            source.summary("aaa"),
+            Err(ArtifactError::RateLimited(format!("{}aaa", url)))
+        );
+        // Only GitHub refuses requests with 403 once too many have been made
+        assert!(matches!(
+            source.summary("bbb"),
+            Err(ArtifactError::Fetch(_))
+        ));
+
+        // Builds after the first rate limited request aren't asked for
+        let url = crate::test_util::serve(vec![
+            ("200 OK", b"first".to_vec()),
+            ("429 Too Many Requests", Vec::new()),
+            ("200 OK", b"third".to_vec()),
+        ]);
+        let source = ChangelogSource::new(&format!("{}{{hash}}", url));
+        let artifacts = vec![
+            artifact(6001, "aaa"),
+            artifact(6002, "bbb"),
+            artifact(6003, "ccc"),
+        ];
+        let mut diff = ArtifactDiff::between(&artifacts, 6000, 6003);
+        assert!(matches!(
+            diff.fetch_summaries(&source),
+            Err(ArtifactError::RateLimited(_))
+        ));
+        let summaries: Vec<Option<&str>> =
+            diff.builds.iter().map(|b| b.summary.as_deref()).collect();
+        assert_eq!(summaries, vec![Some("first"), None, None]);
+
+        let github = ArtifactError::RateLimited(DEFAULT_CHANGELOG_URL.replace("{hash}", "aaa"));
+        assert!(github.to_string().contains("set GITHUB_TOKEN"));
+    }
+}
@@ -0,0 +1,306 @@
+use std::fmt;
+
+use regex::Regex;
+
+use super::{Artifact, ArtifactChannel, ArtifactError};
+
+/// Everything an artifact source could find out about the builds on an artifact server.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ArtifactListing {
+    /// The builds listed, in the order they were found.
+    pub artifacts: Vec<Artifact>,
+    /// The number of the recommended build, if it is named.
+    pub recommended: Option<u16>,
+    /// The number of the optional build, if it is named.
+    pub optional: Option<u16>,
+    /// The number of the latest build, if it is named.
+    pub latest: Option<u16>,
+    /// The oldest build servers should run, as anything older has a critical problem.
+    pub critical: Option<u16>,
+}
+
+impl ArtifactListing {
+    /// The number of the build a channel points at. `Latest` falls back to the newest build
+    /// listed if the source doesn't name one.
+    pub fn channel_num(&self, channel: ArtifactChannel) -> Option<u16> {
+        match channel {
+            ArtifactChannel::Recommended => self.recommended,
+            ArtifactChannel::Optional => self.optional,
+            ArtifactChannel::Latest => self
+                .latest
+                .or_else(|| self.artifacts.iter().map(|a| a.num).max()),
+        }
+    }
+
+    /// The build a channel points at, if it is listed.
+    pub fn channel(&self, channel: ArtifactChannel) -> Option<&Artifact> {
+        self.artifact(self.channel_num(channel)?)
+    }
+
+    /// The build with a number, if it is listed.
+    pub fn artifact(&self, num: u16) -> Option<&Artifact> {
+        self.artifacts.iter().find(|a| a.num == num)
+    }
+
+    /// Adds a build, unless a build with the same number is already listed.
+    fn push(&mut self, artifact: Artifact) {
+        if !self.artifacts.iter().any(|a| a.num == artifact.num) {
+            self.artifacts.push(artifact);
+        }
+    }
+}
+
+/// A way of reading the list of builds from what an artifact server responds with.
+pub trait ArtifactSource: fmt::Debug + Send + Sync {
+    /// A short name for the source, used in errors.
+    fn name(&self) -> &'static str;
+
+    /// Reads the builds from `body`, which was fetched from `url`. If the body isn't in a form
+    /// this source understands, `None` is returned so another source can be tried.
+    fn parse(&self, url: &str, body: &str) -> Option<ArtifactListing>;
+}
+
+/// Reads the builds from `body`, which was fetched from `url`, with the first of `sources` which
+/// recognises it.
+pub fn parse_listing(
+    sources: &[&dyn ArtifactSource],
+    url: &str,
+    body: &str,
+) -> Result<ArtifactListing, ArtifactError> {
+    sources
+        .iter()
+        .find_map(|source| source.parse(url, body))
+        .ok_or_else(|| ArtifactError::Unrecognised {
+            tried: sources.iter().map(|source| source.name()).collect(),
+        })
+}
+
+/// Reads the HTML directory listing of the artifact server, such as
+/// <https://runtime.fivem.net/artifacts/fivem/build_proot_linux/master/>.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HtmlListing;
+
+impl ArtifactSource for HtmlListing {
+    fn name(&self) -> &'static str {
+        "HTML listing"
+    }
+
+    fn parse(&self, url: &str, body: &str) -> Option<ArtifactListing> {
+        // Other sources, such as the JSON changelog, can mention archives too
+        if !body.contains("href=") {
+            return None;
+        }
+        let re = Regex::new(r"(\d+)\-([\da-f]+)/(fx\.tar\.xz|server\.zip)").unwrap();
+        let broken_re = Regex::new(r"(?i)\b(broken|revoked)\b").unwrap();
+        let mut listing = ArtifactListing::default();
+        // Builds are linked relative to the listing
+        let url = url.trim_end_matches('/');
+
+        let captures: Vec<_> = re.captures_iter(body).collect();
+        for (i, capture) in captures.iter().enumerate() {
+            let num = match capture[1].parse::<u16>() {
+                Ok(num) => num,
+                Err(_) => continue,
+            };
+            // The text between this link and the next describes the build
+            let end = captures
+                .get(i + 1)
+                .map(|next| next.get(0).unwrap().start())
+                .unwrap_or(body.len());
+            let broken = broken_re.is_match(&body[capture.get(0).unwrap().end()..end]);
+            if let Some(existing) = listing.artifacts.iter_mut().find(|a| a.num == num) {
+                existing.broken |= broken;
+            } else {
+                listing.push(Artifact {
+                    url: format!("{}/{}-{}/", url, &capture[1], &capture[2]),
+                    num,
+                    hash: capture[2].to_string(),
+                    archive: capture[3].to_string(),
+                    broken,
+                });
+            }
+        }
+        if listing.artifacts.is_empty() {
+            return None;
+        }
+
+        let named = |pattern: &str| {
+            Regex::new(pattern)
+                .unwrap()
+                .captures(body)
+                .and_then(|capture| capture[1].parse::<u16>().ok())
+        };
+        listing.recommended = named(r"RECOMMENDED \((\d+)\)");
+        listing.optional = named(r"OPTIONAL \((\d+)\)");
+        listing.latest = named(r"LATEST \((\d+)\)");
+        Some(listing)
+    }
+}
+
+/// Reads the JSON changelog API, such as
+/// <https://changelogs-live.fivem.net/api/changelog/versions/linux/server>, which names the
+/// build each channel points at and where to download it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonChangelog;
+
+impl ArtifactSource for JsonChangelog {
+    fn name(&self) -> &'static str {
+        "JSON changelog"
+    }
+
+    fn parse(&self, _url: &str, body: &str) -> Option<ArtifactListing> {
+        let json: serde_json::Value = serde_json::from_str(body).ok()?;
+        let object = json.as_object()?;
+        // Numbers are usually given as strings, but accept either
+        let num = |key: &str| match object.get(key)? {
+            serde_json::Value::String(s) => s.trim().parse::<u16>().ok(),
+            serde_json::Value::Number(n) => n.as_u64().and_then(|n| u16::try_from(n).ok()),
+            _ => None,
+        };
+        let download_re = Regex::new(r"^(.*/)(\d+)\-([\da-f]+)/([^/]+)$").unwrap();
+
+        let mut listing = ArtifactListing {
+            recommended: num("recommended"),
+            optional: num("optional"),
+            latest: num("latest"),
+            critical: num("critical"),
+            ..Default::default()
+        };
+        for channel in ["recommended", "optional", "latest", "critical"] {
+            let download = match object
+                .get(&format!("{}_download", channel))
+                .and_then(|d| d.as_str())
+            {
+                Some(download) => download,
+                None => continue,
+            };
+            if let Some(capture) = download_re.captures(download) {
+                if let Ok(num) = capture[2].parse::<u16>() {
+                    listing.push(Artifact {
+                        url: format!("{}{}-{}/", &capture[1], &capture[2], &capture[3]),
+                        num,
+                        hash: capture[3].to_string(),
+                        archive: capture[4].to_string(),
+                        broken: false,
+                    });
+                }
+            }
+        }
+
+        if listing.recommended.is_none()
+            && listing.optional.is_none()
+            && listing.latest.is_none()
+            && listing.artifacts.is_empty()
+        {
+            return None;
+        }
+        Some(listing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINUX_URL: &str = "https://runtime.fivem.net/artifacts/fivem/build_proot_linux/master/";
+
+    #[test]
+    fn html_listing_linux() {
+        let body = include_str!("../../tests/fixtures/artifacts/linux.html");
+        let listing = HtmlListing.parse(LINUX_URL, body).unwrap();
+
+        let nums: Vec<u16> = listing.artifacts.iter().map(|a| a.num).collect();
+        assert_eq!(nums, vec![5848, 6013, 6116, 6097, 6012, 5181]);
+        assert_eq!(
+            listing.channel_num(ArtifactChannel::Recommended),
+            Some(5848)
+        );
+        assert_eq!(listing.channel_num(ArtifactChannel::Optional), Some(6013));
+        assert_eq!(listing.channel_num(ArtifactChannel::Latest), Some(6116));
+        assert_eq!(listing.critical, None);
+
+        let recommended = &listing.artifacts[0];
+        assert_eq!(
+            recommended.url,
+            format!(
+                "{}5848-4f71128ee48b07026d6d7229a60ebc5f40f2b9db/",
+                LINUX_URL
+            )
+        );
+        assert_eq!(recommended.archive, "fx.tar.xz");
+        assert!(!recommended.broken);
+
+        let broken: Vec<u16> = listing
+            .artifacts
+            .iter()
+            .filter(|a| a.broken)
+            .map(|a| a.num)
+            .collect();
+        assert_eq!(broken, vec![6097, 6012]);
+    }
+
+    #[test]
+    fn html_listing_windows() {
+        let url = "https://runtime.fivem.net/artifacts/fivem/build_server_windows/master/";
+        let body = include_str!("../../tests/fixtures/artifacts/windows.html");
+        let listing = HtmlListing.parse(url, body).unwrap();
+
+        assert_eq!(listing.artifacts.len(), 3);
+        assert!(listing.artifacts.iter().all(|a| a.archive == "server.zip"));
+        assert_eq!(
+            listing.channel_num(ArtifactChannel::Recommended),
+            Some(5848)
+        );
+        assert_eq!(listing.channel_num(ArtifactChannel::Optional), None);
+        assert_eq!(listing.channel_num(ArtifactChannel::Latest), Some(6116));
+    }
+
+    #[test]
+    fn json_changelog() {
+        let body = include_str!("../../tests/fixtures/artifacts/changelog.json");
+        let listing = JsonChangelog.parse(LINUX_URL, body).unwrap();
+
+        assert_eq!(
+            listing.channel_num(ArtifactChannel::Recommended),
+            Some(5848)
+        );
+        assert_eq!(listing.channel_num(ArtifactChannel::Optional), Some(6013));
+        assert_eq!(listing.channel_num(ArtifactChannel::Latest), Some(6116));
+        assert_eq!(listing.critical, Some(5181));
+
+        let nums: Vec<u16> = listing.artifacts.iter().map(|a| a.num).collect();
+        assert_eq!(nums, vec![5848, 6013, 6116, 5181]);
+        let latest = &listing.artifacts[2];
+        assert_eq!(
+            latest.url,
+            format!(
+                "{}6116-7a6b5bd8bdf7ab28a4ff0bcd5f0d2a2e9a0d3e58/",
+                LINUX_URL
+            )
+        );
+        assert_eq!(latest.hash, "7a6b5bd8bdf7ab28a4ff0bcd5f0d2a2e9a0d3e58");
+        assert_eq!(latest.archive, "fx.tar.xz");
+    }
+
+    #[test]
+    fn sources_reject_each_others_content() {
+        let html = include_str!("../../tests/fixtures/artifacts/linux.html");
+        let json = include_str!("../../tests/fixtures/artifacts/changelog.json");
+        assert_eq!(JsonChangelog.parse(LINUX_URL, html), None);
+        assert_eq!(HtmlListing.parse(LINUX_URL, json), None);
+    }
+
+    #[test]
+    fn unrecognised_content() {
+        let body = include_str!("../../tests/fixtures/artifacts/unrecognised.html");
+        assert_eq!(HtmlListing.parse(LINUX_URL, body), None);
+        assert_eq!(JsonChangelog.parse(LINUX_URL, body), None);
+        assert_eq!(JsonChangelog.parse(LINUX_URL, "{\"status\": \"ok\"}"), None);
+        assert_eq!(
+            parse_listing(&[&JsonChangelog, &HtmlListing], LINUX_URL, body),
+            Err(ArtifactError::Unrecognised {
+                tried: vec!["JSON changelog", "HTML listing"]
+            })
+        );
+    }
+}
@@ -1,80 +1,444 @@
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
+use std::fmt;
 
+#[cfg(feature = "cli")]
 use colored::*;
+use serde::{Serialize, Serializer};
+
+use crate::secrets;
+
+/// The diff module contains a comparison of what two configs do, ignoring how they are written.
+pub mod diff;
+
+/// The document module contains a lossless representation of `.cfg` files, which can be
+/// edited and written back out without disturbing formatting or comments.
+pub mod document;
+
+/// The format module contains a formatter which writes configs in a consistent style.
+pub mod format;
+
+/// The edit module contains functions for making changes to a config in place, across every
+/// file it `exec`s.
+pub mod edit;
+
+/// The lifecycle module contains a simulator which replays the resource commands in a config
+/// to find out which resources end up running.
+pub mod lifecycle;
+
+pub use document::Document;
+use document::Statement;
+pub use lifecycle::StartedResource;
+use lifecycle::{ResourceCommand, ResourceSimulator};
+
+/// Commands which set the convar of the same name, as in `sv_maxclients 32`.
+pub(crate) const SETTING_COMMANDS: &[&str] = &[
+    "sv_hostname",
+    "sv_projectName",
+    "sv_projectDesc",
+    "sv_maxclients",
+    "sv_licenseKey",
+    "sv_scriptHookAllowed",
+    "sv_endpointPrivacy",
+    "sv_enforceGameBuild",
+    "sv_master1",
+    "sv_lan",
+    "sv_pureLevel",
+    "sv_authMaxVariance",
+    "sv_authMinTrust",
+    "sv_useDirectListing",
+    "sv_listingIPOverride",
+    "sv_listingHostOverride",
+    "sv_forceIndirectListing",
+    "sv_requestParanoia",
+    "sv_filterRequestControl",
+    "sv_enableNetworkedSounds",
+    "sv_enableNetworkedPhoneExplosions",
+    "sv_entityLockdown",
+    "sv_tebexSecret",
+    "sv_playersToken",
+    "sv_profileFriends",
+    "sv_replaceExeToSwitchBuilds",
+    "sv_disableClientReplays",
+    "sv_poolSizesIncrease",
+    "steam_webApiKey",
+    "rcon_password",
+    "onesync",
+    "onesync_enabled",
+    "onesync_population",
+    "onesync_distanceCullVehicles",
+    "onesync_forceMigration",
+    "tags",
+    "locale",
+    "gamename",
+    "netlib",
+    "con_miniconChannels",
+    "con_disableNonTTYReads",
+];
+
+/// The settings `FivemConfig` keeps in fields of their own, such as `hostname`, which
+/// `ConfigDiff` compares as settings rather than as convars.
+pub(crate) const FIELD_SETTINGS: &[&str] = &[
+    "sv_hostname",
+    "sv_maxclients",
+    "sv_scriptHookAllowed",
+    "rcon_password",
+    "sv_licenseKey",
+];
+
+/// Gets the convar a statement sets and the index of the argument holding its value, whether it
+/// is set with `set` (or `sets`, `setr`, `seta`) or with a command of the same name.
+pub(crate) fn setting(statement: &Statement) -> Option<(String, usize)> {
+    let command = statement.command();
+    if ConvarScope::from_command(&command).is_some() {
+        statement.arg(0).map(|name| (name, 1))
+    } else if SETTING_COMMANDS
+        .iter()
+        .any(|c| c.eq_ignore_ascii_case(&command))
+    {
+        Some((command, 0))
+    } else {
+        None
+    }
+}
+
+/// How serious a problem found in a config file is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The config will load, but probably not how it was intended.
+    Warning,
+    /// The config is broken and cannot be fully understood.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A single problem found in a config file, pointing at exactly where it was found.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    /// How serious the problem is.
+    pub severity: Severity,
+    /// A human readable description of the problem.
+    pub message: String,
+    /// The path of the file the problem was found in.
+    pub file: String,
+    /// The line the problem was found on, starting at 1. Zero if the problem concerns the whole file.
+    pub line: usize,
+    /// The column the problem starts at, starting at 1. Zero if the problem concerns the whole file.
+    pub column: usize,
+    /// The directive (the first word of the line) the problem was found in, if any.
+    pub directive: Option<String>,
+    /// The full text of the offending line, if any.
+    pub snippet: Option<String>,
+}
+
+impl Diagnostic {
+    /// Prints the diagnostic to stderr in a format similar to a compiler, using colors as well.
+    #[cfg(feature = "cli")]
+    pub fn print_nicely(&self) {
+        let severity = match self.severity {
+            Severity::Warning => self.severity.to_string().yellow().bold(),
+            Severity::Error => self.severity.to_string().red().bold(),
+        };
+        eprintln!("{}: {}", severity, self.message.bold());
+        if self.line == 0 {
+            eprintln!("  {} {}", "-->".blue().bold(), self.file);
+            return;
+        }
+        eprintln!(
+            "  {} {}:{}:{}",
+            "-->".blue().bold(),
+            self.file,
+            self.line,
+            self.column
+        );
+        if let Some(snippet) = &self.snippet {
+            let gutter = " ".repeat(self.line.to_string().len());
+            eprintln!("{} {}", gutter, "|".blue().bold());
+            eprintln!(
+                "{} {} {}",
+                self.line.to_string().blue().bold(),
+                "|".blue().bold(),
+                snippet
+            );
+            // Underline the word the diagnostic points at
+            let width = snippet
+                .chars()
+                .skip(self.column.saturating_sub(1))
+                .take_while(|c| !c.is_whitespace())
+                .count()
+                .max(1);
+            eprintln!(
+                "{} {} {}{}",
+                gutter,
+                "|".blue().bold(),
+                " ".repeat(self.column.saturating_sub(1)),
+                "^".repeat(width).red().bold()
+            );
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}: {}", self.file, self.severity, self.message)
+        } else {
+            write!(
+                f,
+                "{}:{}:{}: {}: {}",
+                self.file, self.line, self.column, self.severity, self.message
+            )
+        }
+    }
+}
+
+/// The error returned when a config file could not be parsed. This contains every problem
+/// found whilst parsing, including any warnings, rather than just the first error.
+#[derive(Clone, Debug)]
+pub struct ConfigError {
+    /// Every problem found, in the order they were found.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ConfigError {
+    /// The number of diagnostics which are errors (rather than warnings).
+    pub fn error_count(&self) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .count()
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors = self.error_count();
+        write!(
+            f,
+            "{} error{} found whilst parsing the config",
+            errors,
+            if errors == 1 { "" } else { "s" }
+        )
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Which command a convar was set with, and so who can see it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConvarScope {
+    /// Set with `set`, so only visible to the server.
+    Server,
+    /// Set with `sets`, so also shown publicly in the server info.
+    ServerInfo,
+    /// Set with `setr`, so replicated to clients as well as the server.
+    Replicated,
+}
+
+impl ConvarScope {
+    /// Gets the scope a command sets convars in, if the command sets convars.
+    pub fn from_command(command: &str) -> Option<Self> {
+        match command.to_lowercase().as_str() {
+            "set" | "seta" => Some(ConvarScope::Server),
+            "sets" => Some(ConvarScope::ServerInfo),
+            "setr" => Some(ConvarScope::Replicated),
+            _ => None,
+        }
+    }
+
+    /// The command used to set a convar in this scope.
+    pub fn command(&self) -> &'static str {
+        match self {
+            ConvarScope::Server => "set",
+            ConvarScope::ServerInfo => "sets",
+            ConvarScope::Replicated => "setr",
+        }
+    }
+}
+
+/// A single definition of a convar in a config file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Convar {
+    /// The name of the convar.
+    pub name: String,
+    /// The value the convar is set to.
+    pub value: String,
+    /// Which command the convar was set with.
+    pub scope: ConvarScope,
+    /// The path of the file the convar was set in.
+    pub file: String,
+    /// The line the convar was set on, starting at 1.
+    pub line: usize,
+}
+
+impl Convar {
+    /// The warning given when this definition overrides `previous`, an earlier definition of
+    /// the same convar.
+    pub(crate) fn redefinition_warning(&self, previous: &Convar) -> String {
+        if previous.scope == self.scope {
+            format!(
+                "`{}` is set again, overriding the value set at {}:{}",
+                self.name, previous.file, previous.line
+            )
+        } else {
+            format!(
+                "`{}` is set again with `{}`, overriding the value set with `{}` at {}:{}",
+                self.name,
+                self.scope.command(),
+                previous.scope.command(),
+                previous.file,
+                previous.line
+            )
+        }
+    }
+}
 
 /// A struct containing *most* aspects of a FiveM server configuration file.
 /// Some values have been excluded as there are few applications for including them, however
 /// they can still be parsed manually.
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct FivemConfig {
     /// The hostname of the server.
     pub hostname: String,
-    /// A list of resources the server will **attempt** to use
-    pub resources: Vec<String>,
-    /// A list of convars set in the config
-    pub convars: HashMap<String, String>,
-    /// A list of convars replicated both server- and client-side
-    pub convars_replicated: HashMap<String, String>,
+    /// The resources the server will **attempt** to have running once the config has been
+    /// executed, in the order they are started
+    pub resources: Vec<StartedResource>,
+    /// Every definition of a convar in the config, in the order they are set. If a convar is
+    /// set more than once, the last definition wins; see `FivemConfig::convar`. Secret values
+    /// are masked when serialised.
+    #[serde(serialize_with = "serialize_convars")]
+    pub convars: Vec<Convar>,
     /// Whether ScriptHook plugins are permitted by the server
     pub allow_scripthook: bool,
-    /// The RCON password used by the server
+    /// The RCON password used by the server. It is masked when serialised.
+    #[serde(serialize_with = "serialize_masked")]
     pub rcon_password: String,
-    /// The license key used by the server
+    /// The license key used by the server. It is masked when serialised.
+    #[serde(serialize_with = "serialize_masked")]
     pub licensekey: String,
     /// The path (relative) for the icon used by the server
     pub server_icon: String,
     /// The maximum number of clients permitted on the server
     pub max_clients: u16,
+    /// Any warnings found whilst parsing the config
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Splits a hostname into the parts written in each colour, as set by codes such as `^1`. A `^`
+/// which isn't followed by a digit is part of the hostname.
+pub fn hostname_colors(hostname: &str) -> Vec<(u8, String)> {
+    let mut parts = vec![(0, String::new())];
+    let mut chars = hostname.chars().peekable();
+    while let Some(c) = chars.next() {
+        let color = match chars.peek() {
+            Some(next) if c == '^' => next.to_digit(10),
+            _ => None,
+        };
+        match color {
+            Some(color) => {
+                chars.next();
+                parts.push((color as u8, String::new()));
+            }
+            None => parts.last_mut().unwrap().1.push(c),
+        }
+    }
+    parts.retain(|(_, part)| !part.is_empty());
+    parts
+}
+
+/// Serialises a secret in its masked form, so it isn't leaked by JSON or YAML output.
+fn serialize_masked<S: Serializer>(value: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&secrets::mask(value))
+}
+
+/// Serialises convars with the values of secrets masked.
+fn serialize_convars<S: Serializer>(convars: &[Convar], serializer: S) -> Result<S::Ok, S::Error> {
+    let masked: Vec<Convar> = convars
+        .iter()
+        .map(|c| Convar {
+            value: secrets::mask_convar(&c.name, &c.value),
+            ..c.clone()
+        })
+        .collect();
+    masked.serialize(serializer)
+}
+
+impl Default for FivemConfig {
+    fn default() -> Self {
+        Self {
+            hostname: String::new(),
+            resources: Vec::new(),
+            convars: Vec::new(),
+            allow_scripthook: true,
+            rcon_password: String::new(),
+            licensekey: String::new(),
+            server_icon: String::new(),
+            max_clients: 0,
+            diagnostics: Vec::new(),
+        }
+    }
 }
 
 impl FivemConfig {
+    /// Gets the definition of a convar which takes effect (the last one set), if it is set.
+    pub fn convar(&self, name: &str) -> Option<&Convar> {
+        self.convars
+            .iter()
+            .rev()
+            .find(|c| c.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Gets the definition of each convar which takes effect, in the order they were set.
+    pub fn effective_convars(&self) -> Vec<&Convar> {
+        self.convars
+            .iter()
+            .enumerate()
+            .filter(|(i, c)| {
+                !self.convars[i + 1..]
+                    .iter()
+                    .any(|l| l.name.eq_ignore_ascii_case(&c.name))
+            })
+            .map(|(_, c)| c)
+            .collect()
+    }
+
+    /// Gets every definition of a convar which is overridden by a later one.
+    pub fn shadowed_convars(&self, name: &str) -> Vec<&Convar> {
+        let mut defs: Vec<&Convar> = self
+            .convars
+            .iter()
+            .filter(|c| c.name.eq_ignore_ascii_case(name))
+            .collect();
+        defs.pop();
+        defs
+    }
+
     /// Prints the struct out in a nice format for terminals, using colors as well.
+    #[cfg(feature = "cli")]
     pub fn print_nicely(&self) {
         let mut hostname = String::new();
-        let mut hostname_part = String::new();
-        let mut is_escaped = false;
-        let mut current_color = 0u8;
-        for c in self.hostname.chars() {
-            if c == '^' {
-                hostname.push_str(&format!(
-                    "{}",
-                    match current_color {
-                        0 => hostname_part.white(),
-                        1 => hostname_part.red(),
-                        2 => hostname_part.green(),
-                        3 => hostname_part.yellow(),
-                        4 => hostname_part.blue(),
-                        5 => hostname_part.bright_blue(),
-                        6 => hostname_part.magenta(),
-                        _ => hostname_part.white(),
-                    }
-                ));
-                hostname_part = String::new();
-                is_escaped = true;
-            } else if is_escaped {
-                current_color = c.to_string().parse::<u8>().unwrap_or_else(|_| {
-                    panic!("A colour in the hostname is invalid!");
-                });
-                is_escaped = false;
-            } else {
-                hostname_part.push(c);
-            }
+        for (color, part) in hostname_colors(&self.hostname) {
+            hostname.push_str(&format!(
+                "{}",
+                match color {
+                    1 => part.red(),
+                    2 => part.green(),
+                    3 => part.yellow(),
+                    4 => part.blue(),
+                    5 => part.bright_blue(),
+                    6 => part.magenta(),
+                    _ => part.white(),
+                }
+            ));
         }
-        hostname.push_str(&format!(
-            "{}",
-            match current_color {
-                0 => hostname_part.white(),
-                1 => hostname_part.red(),
-                2 => hostname_part.green(),
-                3 => hostname_part.yellow(),
-                4 => hostname_part.blue(),
-                5 => hostname_part.bright_blue(),
-                6 => hostname_part.magenta(),
-                _ => hostname_part.white(),
-            }
-        ));
 
         println!(
             "{}: {}",
@@ -90,73 +454,65 @@ impl FivemConfig {
             }
         );
 
-        let mut rcon_formatted = String::new();
-        if self.rcon_password.len() < 8 {
-            for _ in 0..self.rcon_password.len() {
-                rcon_formatted.push('*');
-            }
-        } else {
-            for _ in 0..(self.rcon_password.len() - 4) {
-                rcon_formatted.push('*');
-            }
-            rcon_formatted.push_str(
-                &self.rcon_password[(self.rcon_password.len() - 4)..self.rcon_password.len()],
-            );
-        }
-        println!("  {}: {}", "Rcon Password".bold(), rcon_formatted);
-
-        let mut lkey_formatted = String::new();
-        if self.licensekey.len() < 8 {
-            for _ in 0..self.licensekey.len() {
-                lkey_formatted.push('*');
-            }
-        } else {
-            for _ in 0..(self.licensekey.len() - 4) {
-                lkey_formatted.push('*');
-            }
-            lkey_formatted
-                .push_str(&self.licensekey[(self.licensekey.len() - 4)..self.licensekey.len()]);
-        }
-        println!("  {}:   {}", "License Key".bold(), lkey_formatted);
+        println!(
+            "  {}: {}",
+            "Rcon Password".bold(),
+            secrets::mask(&self.rcon_password)
+        );
+        println!(
+            "  {}:   {}",
+            "License Key".bold(),
+            secrets::mask(&self.licensekey)
+        );
 
         println!("  {}:   {}", "Server Icon".bold(), self.server_icon);
         println!("  {}:   {}", "Max Clients".bold(), self.max_clients);
 
-        if self.convars.len() > 0 {
-            println!("  {}:", "Convars".bold());
-            let mut i = 0;
-            let max = self.convars.keys().len();
-            for key in self.convars.keys() {
-                let val = &self.convars[key];
-                i = i + 1;
-                if max == i {
-                    println!("   └─ {} = {}", key, val);
-                } else {
-                    println!("   ├─ {} = {}", key, val);
-                }
+        let convars = self.effective_convars();
+        for (scope, title) in [
+            (ConvarScope::Server, "Convars"),
+            (ConvarScope::ServerInfo, "Server Info Convars"),
+            (ConvarScope::Replicated, "Replicated Convars"),
+        ] {
+            let in_scope: Vec<&&Convar> = convars.iter().filter(|c| c.scope == scope).collect();
+            if in_scope.is_empty() {
+                continue;
             }
-        }
-
-        if self.convars_replicated.len() > 0 {
-            println!("  {}:", "Replicated Convars".bold());
-            let mut i = 0;
-            let max = self.convars_replicated.keys().len();
-            for key in self.convars_replicated.keys() {
-                let val = &self.convars_replicated[key];
-                i = i + 1;
-                if max == i {
-                    println!("   └─ {} = {}", key, val);
+            println!("  {}:", title.bold());
+            let max = in_scope.len();
+            for (i, convar) in in_scope.into_iter().enumerate() {
+                let branch = if max == (i + 1) { "└─" } else { "├─" };
+                let shadowed = self.shadowed_convars(&convar.name);
+                let value = secrets::mask_convar(&convar.name, &convar.value);
+                if shadowed.is_empty() {
+                    println!("   {} {} = {}", branch, convar.name, value);
                 } else {
-                    println!("   ├─ {} = {}", key, val);
+                    let overrides: Vec<String> = shadowed
+                        .iter()
+                        .map(|c| format!("{}:{}", c.file, c.line))
+                        .collect();
+                    println!(
+                        "   {} {} = {} {}",
+                        branch,
+                        convar.name,
+                        value,
+                        format!(
+                            "(from {}:{}, overriding {})",
+                            convar.file,
+                            convar.line,
+                            overrides.join(", ")
+                        )
+                        .dimmed()
+                    );
                 }
             }
         }
 
-        if self.resources.len() > 0 {
+        if !self.resources.is_empty() {
             println!("  {}:", "Resources".bold());
             let max = self.resources.len();
             for i in 0..max {
-                let val = &self.resources[i];
+                let val = &self.resources[i].name;
                 if max == (i + 1) {
                     println!("   └─ {}", val);
                 } else {
@@ -167,134 +523,467 @@ impl FivemConfig {
     }
 }
 
-/// An internal function which takes a line from the config file and breaks it up into arguments,
-/// generally by spaces but correctly dealing with quoting
-fn config_line_split(line: String) -> Vec<String> {
-    let mut parts = Vec::new();
-
-    let mut in_text_block = false;
-    let mut part = String::new();
-    for c in line.chars() {
-        if c == '"' {
-            in_text_block = !in_text_block;
-        } else if c == ' ' && !in_text_block {
-            if part.len() != 0 {
-                parts.push(part);
-            }
-            part = String::new();
-        } else {
-            part.push(c);
-        }
-    }
-
-    if part.len() != 0 {
-        parts.push(part);
-    }
+/// An internal struct which keeps track of the state of parsing across `exec`ed files.
+struct ConfigParser {
+    config: FivemConfig,
+    diagnostics: Vec<Diagnostic>,
+    resources: ResourceSimulator,
+    /// The files currently being parsed, used to detect files which `exec` themselves.
+    exec_stack: Vec<String>,
+}
 
-    parts
+/// The statement being parsed and where it came from, used to build diagnostics.
+struct LineContext<'a> {
+    file: &'a str,
+    number: usize,
+    statement: &'a Statement,
+    directive: String,
 }
 
-/// An internal function which takes a file and parses it, saving data into a `FivemConfig` struct.
-fn parse_file(config: &mut FivemConfig, file_name: &str) -> Result<(), &'static str> {
-    let mut file = File::open(file_name).expect("Failed to open main config file.");
-    let mut file_contents = String::new();
-
-    file.read_to_string(&mut file_contents)
-        .expect("Failed to read main config file.");
-    let lines: Vec<String> = file_contents
-        .replace("\r", "\n") // Replace CR newlines with LF (extra blank lines don't matter)
-        .split("\n") // Split on newlines
-        .map(|s: &str| s.to_string())
-        .collect(); // Collect as Vec<String>
-
-    for line in lines {
-        if line.starts_with("#") {
-            continue;
-        }
+impl ConfigParser {
+    fn report(&mut self, severity: Severity, ctx: &LineContext, column: usize, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            message,
+            file: ctx.file.to_string(),
+            line: ctx.number,
+            column,
+            directive: Some(ctx.directive.clone()),
+            snippet: Some(ctx.statement.to_string()),
+        });
+    }
 
-        let parts = config_line_split(line);
-        if parts.len() == 0 {
-            continue;
+    /// Fetch a required argument, reporting an error if it is missing.
+    fn arg(&mut self, ctx: &LineContext, index: usize) -> Option<String> {
+        let arg = ctx.statement.arg(index);
+        if arg.is_none() {
+            self.report(
+                Severity::Error,
+                ctx,
+                ctx.statement.column(index + 1),
+                format!("`{}` is missing an argument", ctx.directive),
+            );
         }
+        arg
+    }
 
-        match parts[0].as_str() {
-            "sv_hostname" => {
-                config.hostname = parts[1].clone();
-            }
-            "start" => {
-                config.resources.push(parts[1].clone());
+    /// Reads and parses a single file, following any `exec` directives. `exec_from` is the
+    /// `exec` directive which included this file, if any.
+    fn parse_file(&mut self, file_name: &str, exec_from: Option<&LineContext>) {
+        if self.exec_stack.iter().any(|f| f == file_name) {
+            if let Some(ctx) = exec_from {
+                self.report(
+                    Severity::Error,
+                    ctx,
+                    ctx.statement.column(1),
+                    format!(
+                        "`{}` is already being executed (recursive `exec`)",
+                        file_name
+                    ),
+                );
             }
-            "ensure" => {
-                if !config.resources.contains(&parts[1].clone()) {
-                    config.resources.push(parts[1].clone());
+            return;
+        }
+
+        let read = match exec_from {
+            Some(_) => Document::read_or_template(file_name),
+            None => Document::read(file_name).map(|doc| (doc, None)),
+        };
+        match read {
+            Ok((doc, template)) => {
+                if let (Some(template), Some(ctx)) = (template, exec_from) {
+                    self.report(
+                        Severity::Warning,
+                        ctx,
+                        ctx.statement.column(1),
+                        format!(
+                            "`{}` doesn't exist, so its template `{}` was checked instead",
+                            file_name, template
+                        ),
+                    );
                 }
+                self.parse_document(&doc)
             }
-            "set" => {
-                config.convars.insert(
-                    parts[1].clone(),
-                    parts.get(2).unwrap_or(&String::new()).clone(),
-                );
-            }
-            "sets" => {
-                config.convars.insert(
-                    parts[1].clone(),
-                    parts.get(2).unwrap_or(&String::new()).clone(),
-                );
+            Err(e) => {
+                let message = format!("failed to read `{}`: {}", file_name, e);
+                match exec_from {
+                    Some(ctx) => {
+                        self.report(Severity::Error, ctx, ctx.statement.column(1), message)
+                    }
+                    None => self.diagnostics.push(Diagnostic {
+                        severity: Severity::Error,
+                        message,
+                        file: file_name.to_string(),
+                        line: 0,
+                        column: 0,
+                        directive: None,
+                        snippet: None,
+                    }),
+                }
             }
-            "setr" => {
-                config.convars_replicated.insert(
-                    parts[1].clone(),
-                    parts.get(2).unwrap_or(&String::new()).clone(),
-                );
+        }
+    }
+
+    /// Parses an already read document, following any `exec` directives.
+    fn parse_document(&mut self, doc: &Document) {
+        self.exec_stack.push(doc.path.clone());
+        for (number, statement) in doc.statements() {
+            let ctx = LineContext {
+                file: &doc.path,
+                number,
+                statement,
+                directive: statement.command(),
+            };
+            if ctx.directive.is_empty() {
+                continue;
             }
-            "sv_scriptHookAllowed" => {
-                config.allow_scripthook = parts[1] == "1";
+            self.parse_directive(&ctx);
+        }
+        self.exec_stack.pop();
+    }
+
+    /// Finishes parsing, returning the config if there were no errors.
+    fn finish(self) -> Result<FivemConfig, ConfigError> {
+        if self
+            .diagnostics
+            .iter()
+            .any(|d| d.severity == Severity::Error)
+        {
+            return Err(ConfigError {
+                diagnostics: self.diagnostics,
+            });
+        }
+        let mut config = self.config;
+        config.resources = self.resources.into_running();
+        config.diagnostics = self.diagnostics;
+        Ok(config)
+    }
+
+    fn parse_directive(&mut self, ctx: &LineContext) {
+        // Commands are matched without regard to case, as the server does
+        let directive = ctx.directive.to_lowercase();
+        if let Some((name, index)) = setting(ctx.statement) {
+            let value = match ConvarScope::from_command(&directive) {
+                Some(scope) => {
+                    self.set_convar(ctx, name.clone(), index, scope);
+                    ctx.statement.arg(index)
+                }
+                // A setting's own command, such as `sv_maxclients 48`, sets it as `set` does
+                None => {
+                    let value = self.arg(ctx, index);
+                    if value.is_some() {
+                        self.set_convar(ctx, name.clone(), index, ConvarScope::Server);
+                    }
+                    value
+                }
+            };
+            if let Some(value) = value {
+                self.apply_setting(ctx, &name, index, value);
             }
-            "rcon_password" => {
-                config.rcon_password = parts[1].clone();
+            return;
+        }
+        match directive.as_str() {
+            "start" | "ensure" | "stop" | "restart" | "refresh" => {
+                let command = ResourceCommand::from_command(&directive).unwrap();
+                let name = if command.takes_resource() {
+                    match self.arg(ctx, 0) {
+                        Some(name) => name,
+                        None => return,
+                    }
+                } else {
+                    String::new()
+                };
+                if let Some(warning) = self.resources.apply(command, &name, ctx.file, ctx.number) {
+                    self.report(Severity::Warning, ctx, ctx.statement.column(1), warning);
+                }
             }
-            "sv_licenseKey" => {
-                config.licensekey = parts[1].clone();
+            "set" | "sets" | "setr" | "seta" => {
+                // Only reached when the name of the convar is missing, which is reported
+                self.arg(ctx, 0);
             }
             "load_server_icon" => {
-                config.server_icon = parts[1].clone();
-            }
-            "sv_maxclients" => {
-                let parse_res = parts[1].parse::<u16>();
-                if parse_res.is_ok() {
-                    config.max_clients = parse_res.ok().unwrap();
-                } else {
-                    return Err("Max clients is not a number!");
+                if let Some(icon) = self.arg(ctx, 0) {
+                    self.config.server_icon = icon;
                 }
             }
             "exec" => {
-                let result = parse_file(config, &parts[1]);
-                if result.is_err() {
-                    return Err(result.err().unwrap());
+                if let Some(file_name) = self.arg(ctx, 0) {
+                    let config = self.exec_stack.first().cloned().unwrap_or_default();
+                    let file_name = document::resolve_exec(&config, &file_name);
+                    self.parse_file(&file_name, Some(ctx));
                 }
             }
             _ => (),
         }
     }
 
-    Ok(())
+    /// Records a convar, whether set with `set` (or `sets`, `setr`, `seta`) or with its own
+    /// command, warning if it overrides an earlier definition. `index` is the argument holding
+    /// the value.
+    fn set_convar(&mut self, ctx: &LineContext, name: String, index: usize, scope: ConvarScope) {
+        let convar = Convar {
+            value: ctx.statement.arg(index).unwrap_or_default(),
+            scope,
+            file: ctx.file.to_string(),
+            line: ctx.number,
+            name,
+        };
+        if let Some(previous) = self.config.convar(&convar.name) {
+            let message = convar.redefinition_warning(previous);
+            self.report(Severity::Warning, ctx, ctx.statement.column(index), message);
+        }
+        self.config.convars.push(convar);
+    }
+
+    /// Applies a setting the config keeps track of, such as the hostname, whether it was set
+    /// with its own command or with `set`. `index` is the argument holding the value.
+    fn apply_setting(&mut self, ctx: &LineContext, name: &str, index: usize, value: String) {
+        match name.to_lowercase().as_str() {
+            "sv_hostname" => self.config.hostname = value,
+            "sv_scripthookallowed" => self.config.allow_scripthook = value == "1",
+            "rcon_password" => self.config.rcon_password = value,
+            "sv_licensekey" => self.config.licensekey = value,
+            "sv_maxclients" => match value.parse::<u16>() {
+                Ok(max) => self.config.max_clients = max,
+                Err(_) => self.report(
+                    Severity::Error,
+                    ctx,
+                    ctx.statement.column(index + 1),
+                    format!("max clients must be a number, found `{}`", value),
+                ),
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Builds a `FivemConfig` from an already parsed document. Any files the document `exec`s are
+/// read from disk.
+///
+/// Parsing does not stop at the first problem. If any errors are found, every problem found
+/// (including warnings) is returned in the `ConfigError`. Otherwise any warnings are available
+/// in `FivemConfig::diagnostics`.
+pub fn read_config_document(doc: &Document) -> Result<FivemConfig, ConfigError> {
+    let mut parser = ConfigParser {
+        config: FivemConfig::default(),
+        diagnostics: Vec::new(),
+        resources: ResourceSimulator::new(),
+        exec_stack: Vec::new(),
+    };
+    parser.parse_document(doc);
+    parser.finish()
 }
 
 /// Reads a FiveM config file located by `file_name` and returns a `FivemConfig` struct containing
 /// details about most of the configuration settings.
-pub fn read_config_file<'a>(file_name: &'a str) -> Result<FivemConfig, &'static str> {
-    let mut config = FivemConfig {
-        hostname: String::new(),
-        resources: Vec::new(),
-        convars: HashMap::new(),
-        convars_replicated: HashMap::new(),
-        allow_scripthook: true,
-        rcon_password: String::new(),
-        licensekey: String::new(),
-        server_icon: String::new(),
-        max_clients: 0,
+///
+/// Parsing does not stop at the first problem. If any errors are found, every problem found
+/// (including warnings) is returned in the `ConfigError`. Otherwise any warnings are available
+/// in `FivemConfig::diagnostics`.
+pub fn read_config_file(file_name: &str) -> Result<FivemConfig, ConfigError> {
+    let mut parser = ConfigParser {
+        config: FivemConfig::default(),
+        diagnostics: Vec::new(),
+        resources: ResourceSimulator::new(),
+        exec_stack: Vec::new(),
     };
+    parser.parse_file(file_name, None);
+    parser.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn config(text: &str) -> FivemConfig {
+        read_config_document(&Document::parse("server.cfg", text)).unwrap()
+    }
+
+    #[test]
+    fn unknown_hostname_colors_are_kept_as_text() {
+        assert_eq!(
+            hostname_colors("^1My ^xServer^^2!^"),
+            vec![(1, "My ^xServer^".to_string()), (2, "!^".to_string()),]
+        );
+        assert_eq!(hostname_colors(""), vec![]);
+    }
+
+    #[test]
+    fn secrets_are_masked_when_serialised() {
+        let cfg = config("rcon_password hunter2hunter\nsv_licenseKey cfxk_abcdef123\n");
+        assert_eq!(cfg.rcon_password, "hunter2hunter");
+        let json = serde_json::to_value(&cfg).unwrap();
+        assert_eq!(json["rcon_password"], "*********nter");
+        assert_eq!(json["licensekey"], "**********f123");
+        assert!(!json.to_string().contains("hunter2hunter"));
+        assert!(!json.to_string().contains("cfxk_abcdef123"));
+    }
+
+    #[test]
+    fn settings_can_be_set_as_convars() {
+        let cfg = config(
+            "set sv_licenseKey cfxk_abcdef123\nsets sv_hostname \"My Server\"\n\
+             seta rcon_password hunter2hunter\nSV_MAXCLIENTS 48\nSv_ScriptHookAllowed 1\n\
+             ENSURE chat\n",
+        );
+        assert_eq!(cfg.licensekey, "cfxk_abcdef123");
+        assert_eq!(cfg.hostname, "My Server");
+        assert_eq!(cfg.rcon_password, "hunter2hunter");
+        assert_eq!(cfg.max_clients, 48);
+        assert!(cfg.allow_scripthook);
+        assert_eq!(cfg.resources.len(), 1);
+        let scopes: Vec<(&str, ConvarScope)> = cfg
+            .convars
+            .iter()
+            .map(|c| (c.name.as_str(), c.scope))
+            .collect();
+        assert_eq!(
+            scopes,
+            vec![
+                ("sv_licenseKey", ConvarScope::Server),
+                ("sv_hostname", ConvarScope::ServerInfo),
+                ("rcon_password", ConvarScope::Server),
+                ("SV_MAXCLIENTS", ConvarScope::Server),
+                ("Sv_ScriptHookAllowed", ConvarScope::Server),
+            ]
+        );
+
+        let doc = Document::parse("server.cfg", "set sv_maxclients lots\n");
+        assert!(read_config_document(&doc).is_err());
+    }
+
+    #[test]
+    fn later_convars_shadow_earlier_ones() {
+        let cfg = config("set a 1\nsets b 2\nSET A 3\nsetr b 4\nset c 5\n");
+        let effective: Vec<(&str, &str)> = cfg
+            .effective_convars()
+            .into_iter()
+            .map(|c| (c.name.as_str(), c.value.as_str()))
+            .collect();
+        assert_eq!(effective, vec![("A", "3"), ("b", "4"), ("c", "5")]);
+        assert_eq!(cfg.convar("a").unwrap().line, 3);
+        let shadowed: Vec<usize> = cfg.shadowed_convars("B").iter().map(|c| c.line).collect();
+        assert_eq!(shadowed, vec![2]);
+        assert!(cfg.shadowed_convars("c").is_empty());
+
+        for (command, scope) in [
+            ("set", ConvarScope::Server),
+            ("SETA", ConvarScope::Server),
+            ("sets", ConvarScope::ServerInfo),
+            ("Setr", ConvarScope::Replicated),
+        ] {
+            assert_eq!(ConvarScope::from_command(command), Some(scope));
+        }
+        assert_eq!(ConvarScope::from_command("ensure"), None);
+        assert_eq!(ConvarScope::ServerInfo.command(), "sets");
+    }
+
+    #[test]
+    fn setting_commands_are_recorded_as_convars() {
+        let cfg = config("set sv_maxclients 32\nonesync on\nsv_maxclients 48\ntags roleplay\n");
+        let convars: Vec<(&str, &str, ConvarScope, usize)> = cfg
+            .convars
+            .iter()
+            .map(|c| (c.name.as_str(), c.value.as_str(), c.scope, c.line))
+            .collect();
+        assert_eq!(
+            convars,
+            vec![
+                ("sv_maxclients", "32", ConvarScope::Server, 1),
+                ("onesync", "on", ConvarScope::Server, 2),
+                ("sv_maxclients", "48", ConvarScope::Server, 3),
+                ("tags", "roleplay", ConvarScope::Server, 4),
+            ]
+        );
+        assert_eq!(cfg.convars[1].file, "server.cfg");
+        assert_eq!(cfg.max_clients, 48);
+        let shadowed: Vec<usize> = cfg
+            .shadowed_convars("sv_maxclients")
+            .iter()
+            .map(|c| c.line)
+            .collect();
+        assert_eq!(shadowed, vec![1]);
+        assert_eq!(cfg.diagnostics.len(), 1);
+        assert_eq!(cfg.diagnostics[0].line, 3);
+        assert_eq!(cfg.diagnostics[0].column, 1);
+        assert_eq!(
+            cfg.diagnostics[0].message,
+            "`sv_maxclients` is set again, overriding the value set at server.cfg:1"
+        );
+    }
 
-    let _ = parse_file(&mut config, file_name)?;
-    Ok(config)
+    #[test]
+    fn every_problem_in_a_file_is_reported() {
+        let doc = Document::parse(
+            "server.cfg",
+            "ensure chat\nload_server_icon\nsv_maxclients lots\n  ensure\n",
+        );
+        let err = read_config_document(&doc).unwrap_err();
+        assert_eq!(err.error_count(), 3);
+        let found: Vec<String> = err.diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            found,
+            vec![
+                "server.cfg:2:17: error: `load_server_icon` is missing an argument",
+                "server.cfg:3:15: error: max clients must be a number, found `lots`",
+                "server.cfg:4:9: error: `ensure` is missing an argument",
+            ]
+        );
+        assert_eq!(
+            err.diagnostics[0].directive.as_deref(),
+            Some("load_server_icon")
+        );
+        assert_eq!(err.diagnostics[2].snippet.as_deref(), Some("  ensure"));
+    }
+
+    #[test]
+    fn recursive_and_unreadable_execs_are_reported() {
+        let dir = temp_dir("execs");
+        let main = dir.join("server.cfg");
+        std::fs::write(&main, "ensure chat\nexec loop.cfg\nexec missing.cfg\n").unwrap();
+        std::fs::write(dir.join("loop.cfg"), "exec server.cfg\n").unwrap();
+
+        let main = main.to_string_lossy().into_owned();
+        let err = read_config_file(&main).unwrap_err();
+        assert_eq!(err.error_count(), 2);
+        let recursive = &err.diagnostics[0];
+        assert!(recursive.file.ends_with("loop.cfg"));
+        assert_eq!((recursive.line, recursive.column), (1, 6));
+        assert!(recursive.message.contains("recursive `exec`"));
+        let unreadable = &err.diagnostics[1];
+        assert_eq!(unreadable.file, main);
+        assert_eq!((unreadable.line, unreadable.column), (3, 6));
+        assert!(unreadable.message.starts_with("failed to read `"));
+        assert!(unreadable.message.contains("missing.cfg"));
+
+        let missing = dir.join("nothing.cfg").to_string_lossy().into_owned();
+        let err = read_config_file(&missing).unwrap_err();
+        assert_eq!(err.diagnostics.len(), 1);
+        assert_eq!(err.diagnostics[0].file, missing);
+        assert_eq!(err.diagnostics[0].line, 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unrendered_secrets_are_read_from_their_template() {
+        let dir = temp_dir("secrets-template");
+        let main = dir.join("server.cfg");
+        std::fs::write(&main, "exec secrets.cfg\nensure chat\n").unwrap();
+        std::fs::write(
+            dir.join("secrets.cfg.template"),
+            "# Rendered into secrets.cfg by `fivem-utility secrets render`\n\
+             sv_licenseKey \"${SV_LICENSEKEY}\"\n",
+        )
+        .unwrap();
+
+        let cfg = read_config_file(&main.to_string_lossy()).unwrap();
+        assert_eq!(cfg.licensekey, "${SV_LICENSEKEY}");
+        assert_eq!(cfg.diagnostics.len(), 1);
+        assert_eq!(cfg.diagnostics[0].severity, Severity::Warning);
+        assert!(cfg.diagnostics[0]
+            .message
+            .ends_with("secrets.cfg.template` was checked instead"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
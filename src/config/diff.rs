@@ -0,0 +1,413 @@
+use std::collections::BTreeMap;
+
+#[cfg(feature = "cli")]
+use colored::*;
+use serde::Serialize;
+
+use super::{ConvarScope, FivemConfig, FIELD_SETTINGS};
+use crate::secrets;
+
+/// A setting which is different between two configs.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SettingChange {
+    /// The name of the setting, such as `Max Clients`.
+    pub name: &'static str,
+    /// The value in the old config.
+    pub old: String,
+    /// The value in the new config.
+    pub new: String,
+}
+
+/// A convar which is added, removed or changed between two configs. A convar which is set with a
+/// different command is removed from one scope and added to the other.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ConvarChange {
+    /// The name of the convar.
+    pub name: String,
+    /// The scope the convar is set in.
+    pub scope: ConvarScope,
+    /// The value in the old config, if it was set there.
+    pub old: Option<String>,
+    /// The value in the new config, if it is set there.
+    pub new: Option<String>,
+}
+
+/// A resource which is started in both configs, but in a different order relative to the others.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MovedResource {
+    /// The name of the resource.
+    pub name: String,
+    /// Where the resource was started in the old config, starting at 1.
+    pub old_position: usize,
+    /// Where the resource is started in the new config, starting at 1.
+    pub new_position: usize,
+}
+
+/// The differences between what two configs do, as found by [`ConfigDiff::between`]. Only the
+/// effect of each config is compared, so formatting, comments and how the configs are split
+/// across files make no difference. Secrets are masked.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ConfigDiff {
+    /// The hostname, max clients, ScriptHook and other settings which are different.
+    pub settings: Vec<SettingChange>,
+    /// The convars which are different, grouped by scope and then sorted by name.
+    pub convars: Vec<ConvarChange>,
+    /// The resources which are only started by the new config, in the order they are started.
+    pub resources_added: Vec<String>,
+    /// The resources which are only started by the old config, in the order they were started.
+    pub resources_removed: Vec<String>,
+    /// The resources started by both configs which have been moved.
+    pub resources_moved: Vec<MovedResource>,
+}
+
+/// The positions in `old` and `new` of the longest sequence of resources started in the same
+/// order by both.
+fn longest_common_order(old: &[&str], new: &[&str]) -> Vec<(usize, usize)> {
+    // lengths[i][j] is the length of the longest common sequence of old[i..] and new[j..]
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut common = Vec::new();
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            common.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    common
+}
+
+impl ConfigDiff {
+    /// Compares two configs.
+    pub fn between(old: &FivemConfig, new: &FivemConfig) -> Self {
+        let mut diff = Self::default();
+
+        let scripthook = |allowed| if allowed { "Allowed" } else { "Disabled" }.to_string();
+        // Each setting, with whether it is a secret. Secrets are compared before they are
+        // masked, as two secrets can have the same mask.
+        for (name, old_value, new_value, secret) in [
+            (
+                "Hostname",
+                old.hostname.clone(),
+                new.hostname.clone(),
+                false,
+            ),
+            (
+                "Max Clients",
+                old.max_clients.to_string(),
+                new.max_clients.to_string(),
+                false,
+            ),
+            (
+                "Script Hook",
+                scripthook(old.allow_scripthook),
+                scripthook(new.allow_scripthook),
+                false,
+            ),
+            (
+                "Rcon Password",
+                old.rcon_password.clone(),
+                new.rcon_password.clone(),
+                true,
+            ),
+            (
+                "License Key",
+                old.licensekey.clone(),
+                new.licensekey.clone(),
+                true,
+            ),
+            (
+                "Server Icon",
+                old.server_icon.clone(),
+                new.server_icon.clone(),
+                false,
+            ),
+        ] {
+            if old_value != new_value {
+                let shown = |value: String| if secret { secrets::mask(&value) } else { value };
+                diff.settings.push(SettingChange {
+                    name,
+                    old: shown(old_value),
+                    new: shown(new_value),
+                });
+            }
+        }
+
+        // Convar names are matched without regard to case, as the server does. Those kept as
+        // settings have been compared above.
+        let mut convars: BTreeMap<(ConvarScope, String), ConvarChange> = BTreeMap::new();
+        for (convar, is_new) in old
+            .effective_convars()
+            .into_iter()
+            .map(|c| (c, false))
+            .chain(new.effective_convars().into_iter().map(|c| (c, true)))
+            .filter(|(c, _)| {
+                !FIELD_SETTINGS
+                    .iter()
+                    .any(|s| s.eq_ignore_ascii_case(&c.name))
+            })
+        {
+            let change = convars
+                .entry((convar.scope, convar.name.to_lowercase()))
+                .or_insert_with(|| ConvarChange {
+                    name: convar.name.clone(),
+                    scope: convar.scope,
+                    old: None,
+                    new: None,
+                });
+            if is_new {
+                change.name = convar.name.clone();
+                change.new = Some(convar.value.clone());
+            } else {
+                change.old = Some(convar.value.clone());
+            }
+        }
+        diff.convars = convars
+            .into_values()
+            .filter(|c| c.old != c.new)
+            .map(|mut c| {
+                c.old = c.old.map(|value| secrets::mask_convar(&c.name, &value));
+                c.new = c.new.map(|value| secrets::mask_convar(&c.name, &value));
+                c
+            })
+            .collect();
+
+        let old_resources: Vec<&str> = old.resources.iter().map(|r| r.name.as_str()).collect();
+        let new_resources: Vec<&str> = new.resources.iter().map(|r| r.name.as_str()).collect();
+        diff.resources_added = new_resources
+            .iter()
+            .filter(|name| !old_resources.contains(name))
+            .map(|name| name.to_string())
+            .collect();
+        diff.resources_removed = old_resources
+            .iter()
+            .filter(|name| !new_resources.contains(name))
+            .map(|name| name.to_string())
+            .collect();
+
+        // Resources which keep their order relative to each other haven't moved, even if
+        // resources before them were added or removed
+        let kept_old: Vec<&str> = old_resources
+            .iter()
+            .copied()
+            .filter(|name| new_resources.contains(name))
+            .collect();
+        let kept_new: Vec<&str> = new_resources
+            .iter()
+            .copied()
+            .filter(|name| old_resources.contains(name))
+            .collect();
+        let in_order: Vec<usize> = longest_common_order(&kept_old, &kept_new)
+            .into_iter()
+            .map(|(_, j)| j)
+            .collect();
+        let position = |resources: &[&str], name: &str| {
+            resources
+                .iter()
+                .position(|r| *r == name)
+                .unwrap_or_default()
+                + 1
+        };
+        diff.resources_moved = kept_new
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| !in_order.contains(j))
+            .map(|(_, name)| MovedResource {
+                name: name.to_string(),
+                old_position: position(&old_resources, name),
+                new_position: position(&new_resources, name),
+            })
+            .collect();
+
+        diff
+    }
+
+    /// Whether the configs do the same thing.
+    pub fn is_empty(&self) -> bool {
+        self.settings.is_empty()
+            && self.convars.is_empty()
+            && self.resources_added.is_empty()
+            && self.resources_removed.is_empty()
+            && self.resources_moved.is_empty()
+    }
+
+    /// Prints the differences out in a nice format for terminals, using colors as well.
+    #[cfg(feature = "cli")]
+    pub fn print_nicely(&self) {
+        if !self.settings.is_empty() {
+            println!("{}:", "Settings".bold());
+            for setting in &self.settings {
+                println!(
+                    "  {} {}: {} -> {}",
+                    "~".yellow(),
+                    setting.name,
+                    setting.old.red(),
+                    setting.new.green()
+                );
+            }
+        }
+
+        for (scope, title) in [
+            (ConvarScope::Server, "Convars"),
+            (ConvarScope::ServerInfo, "Server Info Convars"),
+            (ConvarScope::Replicated, "Replicated Convars"),
+        ] {
+            let in_scope: Vec<&ConvarChange> =
+                self.convars.iter().filter(|c| c.scope == scope).collect();
+            if in_scope.is_empty() {
+                continue;
+            }
+            println!("{}:", title.bold());
+            for convar in in_scope {
+                match (&convar.old, &convar.new) {
+                    (Some(old), Some(new)) => println!(
+                        "  {} {}: {} -> {}",
+                        "~".yellow(),
+                        convar.name,
+                        old.red(),
+                        new.green()
+                    ),
+                    (None, Some(new)) => {
+                        println!("  {} {} = {}", "+".green(), convar.name, new.green())
+                    }
+                    (Some(old), None) => {
+                        println!("  {} {} = {}", "-".red(), convar.name, old.red())
+                    }
+                    (None, None) => (),
+                }
+            }
+        }
+
+        if !self.resources_added.is_empty()
+            || !self.resources_removed.is_empty()
+            || !self.resources_moved.is_empty()
+        {
+            println!("{}:", "Resources".bold());
+            for name in &self.resources_added {
+                println!("  {} {}", "+".green(), name.green());
+            }
+            for name in &self.resources_removed {
+                println!("  {} {}", "-".red(), name.red());
+            }
+            for moved in &self.resources_moved {
+                println!(
+                    "  {} {} {}",
+                    "~".yellow(),
+                    moved.name,
+                    format!(
+                        "(moved from #{} to #{})",
+                        moved.old_position, moved.new_position
+                    )
+                    .dimmed()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{read_config_document, Document};
+
+    fn config(text: &str) -> FivemConfig {
+        read_config_document(&Document::parse("server.cfg", text)).unwrap()
+    }
+
+    #[test]
+    fn compares_what_configs_do() {
+        let old = config(
+            "sv_hostname \"Old\"\nsv_maxclients 32\nrcon_password hunter2hunter2\n\
+             set OneSync on\nsets tags \"rp\"\nset removed 1\nset Locale en-GB\n\
+             ensure chat\nensure a\nensure b\nensure c\nensure gone\n",
+        );
+        let new = config(
+            "# Reformatted\nsv_maxclients   48\nsv_hostname \"Old\"\n\
+             rcon_password hunter2hunter3\nset onesync legacy\nsetr tags \"rp\"\n\
+             set locale en-GB\n\
+             ensure chat\nensure new\nensure b\nensure c\nensure a\n",
+        );
+        let diff = ConfigDiff::between(&old, &new);
+
+        assert_eq!(
+            diff.settings,
+            vec![
+                SettingChange {
+                    name: "Max Clients",
+                    old: "32".to_string(),
+                    new: "48".to_string(),
+                },
+                SettingChange {
+                    name: "Rcon Password",
+                    old: "**********ter2".to_string(),
+                    new: "**********ter3".to_string(),
+                },
+            ]
+        );
+        let convars: Vec<(ConvarScope, &str, Option<&str>, Option<&str>)> = diff
+            .convars
+            .iter()
+            .map(|c| (c.scope, c.name.as_str(), c.old.as_deref(), c.new.as_deref()))
+            .collect();
+        assert_eq!(
+            convars,
+            vec![
+                (ConvarScope::Server, "onesync", Some("on"), Some("legacy")),
+                (ConvarScope::Server, "removed", Some("1"), None),
+                (ConvarScope::ServerInfo, "tags", Some("rp"), None),
+                (ConvarScope::Replicated, "tags", None, Some("rp")),
+            ]
+        );
+        assert_eq!(diff.resources_added, vec!["new"]);
+        assert_eq!(diff.resources_removed, vec!["gone"]);
+        assert_eq!(
+            diff.resources_moved,
+            vec![MovedResource {
+                name: "a".to_string(),
+                old_position: 2,
+                new_position: 5,
+            }]
+        );
+
+        assert!(ConfigDiff::between(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn compares_settings_written_as_commands() {
+        let diff = ConfigDiff::between(
+            &config("onesync on\nsv_maxclients 32\n"),
+            &config("onesync off\nsv_maxclients 32\ntags rp\n"),
+        );
+        assert_eq!(
+            diff.convars,
+            vec![
+                ConvarChange {
+                    name: "onesync".to_string(),
+                    scope: ConvarScope::Server,
+                    old: Some("on".to_string()),
+                    new: Some("off".to_string()),
+                },
+                ConvarChange {
+                    name: "tags".to_string(),
+                    scope: ConvarScope::Server,
+                    old: None,
+                    new: Some("rp".to_string()),
+                },
+            ]
+        );
+        assert!(diff.settings.is_empty());
+    }
+}
@@ -0,0 +1,468 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::secrets;
+
+/// A config file parsed losslessly into its lines. Unlike `FivemConfig`, this keeps comments,
+/// blank lines, unknown commands, ordering and formatting, so that writing it back out with
+/// `to_string` gives exactly the original text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Document {
+    /// The path the document was read from.
+    pub path: String,
+    /// Every line in the document, in order.
+    pub lines: Vec<Line>,
+}
+
+/// A single line of a config file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    /// What the line contains.
+    pub item: Item,
+    /// The newline sequence which ends the line. This is `"\n"`, `"\r\n"`, `"\r"` or empty if
+    /// the line is the last in a file without a trailing newline.
+    pub ending: String,
+}
+
+/// The content of a single line of a config file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Item {
+    /// A line with nothing but whitespace on it. The whitespace is kept.
+    Blank(String),
+    /// A comment (starting with `#` or `//`), including any whitespace before it.
+    Comment(String),
+    /// An `exec` statement, which includes another config file.
+    Exec(Statement),
+    /// Any other statement, such as `set`, `ensure` or a command not known by this crate.
+    Statement(Statement),
+}
+
+/// A statement made up of a command and its arguments, keeping the original spacing and quoting.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Statement {
+    /// The tokens that make up the statement. The first is the command.
+    pub tokens: Vec<Token>,
+    /// Any whitespace after the last token.
+    pub trailing: String,
+}
+
+/// A single word of a statement.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    /// The whitespace before the token.
+    pub leading: String,
+    /// The token exactly as it was written, including any quotes.
+    pub raw: String,
+}
+
+impl Token {
+    /// Creates a token with the given value, quoting it only if needed.
+    pub fn new(value: &str) -> Self {
+        Self {
+            leading: String::new(),
+            raw: quote(value),
+        }
+    }
+
+    /// The value of the token with the quotes around it removed. Quotes inside the value can't
+    /// be escaped, so any which aren't around the value are kept as they are.
+    pub fn value(&self) -> String {
+        match self.raw.strip_prefix('"') {
+            Some(quoted) => quoted.strip_suffix('"').unwrap_or(quoted).to_string(),
+            None => self.raw.clone(),
+        }
+    }
+}
+
+/// The path of the file an `exec` of `path` runs. The server looks for relative paths in its
+/// working directory, which is the directory of the main config, `config`.
+pub fn resolve_exec(config: &str, path: &str) -> String {
+    match Path::new(config).parent() {
+        Some(dir) if Path::new(path).is_relative() && !dir.as_os_str().is_empty() => {
+            dir.join(path).to_string_lossy().into_owned()
+        }
+        _ => path.to_string(),
+    }
+}
+
+/// The path to `exec` the file at `path` by from the main config, `config`: relative to the
+/// directory of the main config if the file is inside it, so the config still works when the
+/// server is moved or run by a different user.
+pub fn exec_path(config: &str, path: &str) -> String {
+    let dir = Path::new(config).parent().unwrap_or(Path::new(""));
+    // `path::absolute` refuses the empty directory of a config in the working directory, so
+    // relative paths are joined onto the working directory instead
+    let absolute = |p: &Path| match env::current_dir() {
+        Ok(cwd) if p.is_relative() => cwd.join(p),
+        _ => p.to_path_buf(),
+    };
+    match absolute(Path::new(path)).strip_prefix(absolute(dir)) {
+        Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+        Err(_) => path.to_string(),
+    }
+}
+
+/// Whether a value can be written in a config. Quotes can't be escaped and a newline ends the
+/// line, so values containing either can't be.
+pub fn quotable(value: &str) -> bool {
+    !value.contains(['"', '\n', '\r'])
+}
+
+/// Whether a value must be quoted for the server to read it as a single argument: it is empty,
+/// contains whitespace or a quote, contains a `;` (which would otherwise separate commands), or
+/// starts with `#` or `//` (which would otherwise start a comment).
+pub fn needs_quotes(value: &str) -> bool {
+    value.is_empty()
+        || value.contains([' ', '\t', '"', ';'])
+        || value.starts_with('#')
+        || value.starts_with("//")
+}
+
+/// Quotes a value if it [`needs_quotes`]. Values which aren't [`quotable`] should be refused
+/// before they get here, as any quotes in them are lost.
+pub fn quote(value: &str) -> String {
+    if needs_quotes(value) {
+        format!("\"{}\"", value.replace('"', ""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl Statement {
+    /// Creates a statement from a command and its arguments, separated by single spaces and
+    /// quoted only where needed.
+    pub fn new(command: &str, args: &[&str]) -> Self {
+        let mut tokens = vec![Token::new(command)];
+        for arg in args {
+            let mut token = Token::new(arg);
+            token.leading = " ".to_string();
+            tokens.push(token);
+        }
+        Self {
+            tokens,
+            trailing: String::new(),
+        }
+    }
+
+    /// The command the statement runs (the first token), without quoting.
+    pub fn command(&self) -> String {
+        self.tokens.first().map(|t| t.value()).unwrap_or_default()
+    }
+
+    /// The argument at `index` (zero being the first argument after the command), without
+    /// quoting.
+    pub fn arg(&self, index: usize) -> Option<String> {
+        self.tokens.get(index + 1).map(|t| t.value())
+    }
+
+    /// All arguments after the command, without quoting.
+    pub fn args(&self) -> Vec<String> {
+        self.tokens.iter().skip(1).map(|t| t.value()).collect()
+    }
+
+    /// The column (starting at 1) that the token at `index` starts at, where zero is the
+    /// command. If there is no such token, the column just after the last token is returned.
+    pub fn column(&self, index: usize) -> usize {
+        let mut column = 1;
+        for (i, token) in self.tokens.iter().enumerate() {
+            column += token.leading.chars().count();
+            if i == index {
+                return column;
+            }
+            column += token.raw.chars().count();
+        }
+        column
+    }
+
+    /// Replaces the argument at `index` (zero being the first argument after the command),
+    /// keeping the spacing around it. If the argument doesn't exist, it is added.
+    pub fn set_arg(&mut self, index: usize, value: &str) {
+        let index = index + 1;
+        if let Some(token) = self.tokens.get_mut(index) {
+            token.raw = quote(value);
+        } else {
+            while self.tokens.len() <= index {
+                let mut token = Token::new("");
+                token.leading = " ".to_string();
+                self.tokens.push(token);
+            }
+            self.tokens[index].raw = quote(value);
+        }
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in &self.tokens {
+            write!(f, "{}{}", token.leading, token.raw)?;
+        }
+        write!(f, "{}", self.trailing)
+    }
+}
+
+impl Item {
+    /// Parses the text of a single line (without its newline).
+    pub fn parse(text: &str) -> Self {
+        let content = text.trim_start_matches([' ', '\t']);
+        if content.is_empty() {
+            return Item::Blank(text.to_string());
+        }
+        if content.starts_with('#') || content.starts_with("//") {
+            return Item::Comment(text.to_string());
+        }
+
+        let statement = parse_statement(text);
+        if statement.command().eq_ignore_ascii_case("exec") {
+            Item::Exec(statement)
+        } else {
+            Item::Statement(statement)
+        }
+    }
+
+    /// The statement on this line, if it is a statement (including `exec`).
+    pub fn statement(&self) -> Option<&Statement> {
+        match self {
+            Item::Exec(s) | Item::Statement(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The statement on this line, if it is a statement (including `exec`).
+    pub fn statement_mut(&mut self) -> Option<&mut Statement> {
+        match self {
+            Item::Exec(s) | Item::Statement(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Item::Blank(text) | Item::Comment(text) => write!(f, "{}", text),
+            Item::Exec(s) | Item::Statement(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+/// An internal function which breaks a line up into tokens, generally by spaces but correctly
+/// dealing with quoting. Quotes toggle whether spaces separate tokens, wherever they appear.
+fn parse_statement(text: &str) -> Statement {
+    let mut tokens = Vec::new();
+    let mut whitespace = String::new();
+    let mut raw = String::new();
+    let mut in_text_block = false;
+
+    for c in text.chars() {
+        if (c == ' ' || c == '\t') && !in_text_block {
+            if !raw.is_empty() {
+                tokens.push(Token {
+                    leading: std::mem::take(&mut whitespace),
+                    raw: std::mem::take(&mut raw),
+                });
+            }
+            whitespace.push(c);
+        } else {
+            if c == '"' {
+                in_text_block = !in_text_block;
+            }
+            raw.push(c);
+        }
+    }
+
+    if !raw.is_empty() {
+        tokens.push(Token {
+            leading: std::mem::take(&mut whitespace),
+            raw,
+        });
+    }
+
+    Statement {
+        tokens,
+        trailing: whitespace,
+    }
+}
+
+impl Document {
+    /// Parses the text of a config file. `path` is recorded for use in diagnostics and when
+    /// writing the document back out.
+    pub fn parse(path: &str, text: &str) -> Self {
+        let mut lines = Vec::new();
+        let mut rest = text;
+        while !rest.is_empty() {
+            let (content, ending, next) = match rest.find(['\r', '\n']) {
+                Some(i) if rest[i..].starts_with("\r\n") => (&rest[..i], "\r\n", &rest[i + 2..]),
+                Some(i) => (&rest[..i], &rest[i..i + 1], &rest[i + 1..]),
+                None => (rest, "", ""),
+            };
+            lines.push(Line {
+                item: Item::parse(content),
+                ending: ending.to_string(),
+            });
+            rest = next;
+        }
+
+        Self {
+            path: path.to_string(),
+            lines,
+        }
+    }
+
+    /// Reads and parses the config file at `path`.
+    pub fn read(path: &str) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Ok(Self::parse(path, &text))
+    }
+
+    /// Reads a file `exec`ed by a config so it can be checked. A secrets file which hasn't been
+    /// rendered yet, as in CI where it is ignored by git, is read from its template instead,
+    /// keeping the path of the secrets file so the `exec` still finds it. The path of the
+    /// template is returned if it was read.
+    pub fn read_or_template(path: &str) -> io::Result<(Self, Option<String>)> {
+        match Self::read(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let template = format!("{}{}", path, secrets::TEMPLATE_SUFFIX);
+                match fs::read_to_string(&template) {
+                    Ok(text) => Ok((Self::parse(path, &text), Some(template))),
+                    Err(_) => Err(e),
+                }
+            }
+            result => result.map(|doc| (doc, None)),
+        }
+    }
+
+    /// Writes the document back to the file it was read from.
+    pub fn write(&self) -> io::Result<()> {
+        fs::write(&self.path, self.to_string())
+    }
+
+    /// Every statement (including `exec` statements) in the document, with the line number
+    /// (starting at 1) it is found on.
+    pub fn statements(&self) -> impl Iterator<Item = (usize, &Statement)> {
+        self.lines
+            .iter()
+            .enumerate()
+            .filter_map(|(i, line)| line.item.statement().map(|s| (i + 1, s)))
+    }
+
+    /// The paths of every file this document includes with `exec`, in order.
+    pub fn execs(&self) -> Vec<String> {
+        self.lines
+            .iter()
+            .filter_map(|line| match &line.item {
+                Item::Exec(s) => s.arg(0),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Inserts a new line containing `item` after the line at `index` (starting at 0), or at
+    /// the start of the document if `index` is `None`.
+    pub fn insert_after(&mut self, index: Option<usize>, item: Item) {
+        let newline = self.newline().to_string();
+        let position = index.map(|i| i + 1).unwrap_or(0);
+        let mut ending = newline.clone();
+        if position == self.lines.len() {
+            // The last line may not have had a newline, so move it onto the new last line.
+            if let Some(last) = self.lines.last_mut() {
+                ending = std::mem::replace(&mut last.ending, newline);
+            }
+        }
+        self.lines.insert(position, Line { item, ending });
+    }
+
+    /// Adds a new line containing `item` to the end of the document.
+    pub fn push(&mut self, item: Item) {
+        self.insert_after(self.lines.len().checked_sub(1), item);
+    }
+
+    /// Removes the line at `index` (starting at 0), returning it.
+    pub fn remove(&mut self, index: usize) -> Line {
+        let line = self.lines.remove(index);
+        if index == self.lines.len() && line.ending.is_empty() {
+            // The removed line was the last and had no newline, so neither should the new last.
+            if let Some(last) = self.lines.last_mut() {
+                last.ending = String::new();
+            }
+        }
+        line
+    }
+
+    /// The newline sequence most used in the document, used when adding new lines.
+    pub fn newline(&self) -> &str {
+        let crlf = self.lines.iter().filter(|l| l.ending == "\r\n").count();
+        let lf = self.lines.iter().filter(|l| l.ending == "\n").count();
+        if crlf > lf {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+}
+
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            write!(f, "{}{}", line.item, line.ending)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn documents_are_written_back_exactly() {
+        for text in [
+            "set a 1\r\nensure chat\r\n",
+            "set a 1\rensure chat\r\n# mixed\n",
+            "set a 1\nensure chat",
+            "\tset\t a  \t1 \t\n  \t\n\t# indented\n",
+            "sets sv_projectDesc \"# not a comment\"\nset url \"http://example.com\" // x\n",
+            "# only\n// comments\n  #here\n",
+            "",
+            "\n\r\n\r",
+        ] {
+            let doc = Document::parse("server.cfg", text);
+            assert_eq!(doc.to_string(), text);
+        }
+
+        let doc = Document::parse(
+            "server.cfg",
+            "# a\n  // b\nsets sv_projectDesc \"# not a comment\"\nset url \"a // b\"\n",
+        );
+        let kinds: Vec<bool> = doc
+            .lines
+            .iter()
+            .map(|l| matches!(l.item, Item::Comment(_)))
+            .collect();
+        assert_eq!(kinds, vec![true, true, false, false]);
+        let statements: Vec<String> = doc.statements().map(|(_, s)| s.args()[1].clone()).collect();
+        assert_eq!(statements, vec!["# not a comment", "a // b"]);
+
+        let doc = Document::parse("server.cfg", "exec a.cfg\nEXEC \"b.cfg\"\nExec\n");
+        assert!(matches!(doc.lines[1].item, Item::Exec(_)));
+        assert_eq!(doc.execs(), vec!["a.cfg", "b.cfg"]);
+
+        let doc = Document::parse("server.cfg", "set a 1\rset b 2");
+        assert_eq!(doc.lines[0].ending, "\r");
+        assert_eq!(doc.lines[1].ending, "");
+    }
+
+    #[test]
+    fn token_values_only_lose_surrounding_quotes() {
+        let statement = Item::parse("set \"a b\" say\"hi\" \"open \"\" x\"y\"z");
+        let args: Vec<String> = statement.statement().unwrap().args();
+        assert_eq!(args, vec!["a b", "say\"hi\"", "open \"\" x\"y\"z"]);
+
+        for value in ["plain", "with space", "", "tab\there"] {
+            assert_eq!(Token::new(value).value(), value);
+        }
+        assert_eq!(Token::new("a\"b").raw, "\"ab\"");
+    }
+}
@@ -0,0 +1,605 @@
+use std::fmt;
+use std::io;
+
+use super::document::{quotable, resolve_exec, Document, Item, Statement, Token};
+use super::{setting, ConvarScope};
+
+/// The commands which define a convar.
+const CONVAR_COMMANDS: [&str; 4] = ["set", "sets", "setr", "seta"];
+/// The commands which start a resource.
+const START_COMMANDS: [&str; 2] = ["ensure", "start"];
+
+/// The error returned when an edit couldn't be made.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EditError {
+    /// The convar to change isn't defined anywhere in the config.
+    ConvarNotFound(String),
+    /// The resource to change isn't started anywhere in the config.
+    ResourceNotFound(String),
+    /// The resource is already started by the config.
+    ResourceAlreadyStarted(String),
+    /// A file the edit needed couldn't be read.
+    Unreadable(String),
+    /// A value contains a quote or a newline, so can't be written in a config.
+    Unquotable(String),
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::ConvarNotFound(name) => write!(f, "convar `{}` is never set", name),
+            EditError::ResourceNotFound(name) => write!(f, "resource `{}` is never started", name),
+            EditError::ResourceAlreadyStarted(name) => {
+                write!(f, "resource `{}` is already started", name)
+            }
+            EditError::Unreadable(message) => write!(f, "{}", message),
+            EditError::Unquotable(value) => write!(
+                f,
+                "`{}` contains a quote or a newline, which can't be written in a config",
+                value
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+/// Every document making up a config: the main file, followed by the files it `exec`s in the
+/// order they are executed. Edits are made to whichever file they belong in, and can be written
+/// back without disturbing formatting or comments.
+#[derive(Clone, Debug)]
+pub struct ConfigTree {
+    /// The documents, in the order they are first executed.
+    pub documents: Vec<Document>,
+    /// The text of each document when it was loaded.
+    originals: Vec<String>,
+    /// Whether secrets files which haven't been rendered are read from their templates.
+    templates: bool,
+}
+
+impl ConfigTree {
+    /// Loads the config file at `path` and every file it `exec`s. Files which can't be read
+    /// (other than the main file) are skipped, as `verify` will report them.
+    pub fn load(path: &str) -> io::Result<Self> {
+        Ok(Self::from_document(Document::read(path)?))
+    }
+
+    /// Creates a tree from an already parsed main document, reading any files it `exec`s.
+    pub fn from_document(doc: Document) -> Self {
+        let mut tree = Self {
+            documents: Vec::new(),
+            originals: Vec::new(),
+            templates: false,
+        };
+        tree.add(doc);
+        tree
+    }
+
+    /// Creates a tree from an already parsed main document to check it, as
+    /// [`from_document`](Self::from_document) does, except that secrets files which haven't been
+    /// rendered are read from their templates (see [`Document::read_or_template`]). The tree
+    /// shouldn't be edited, as writing it would write the templates over the secrets files.
+    pub fn checked(doc: Document) -> Self {
+        let mut tree = Self {
+            documents: Vec::new(),
+            originals: Vec::new(),
+            templates: true,
+        };
+        tree.add(doc);
+        tree
+    }
+
+    fn add(&mut self, doc: Document) {
+        let execs = doc.execs();
+        self.originals.push(doc.to_string());
+        self.documents.push(doc);
+        let config = self.documents[0].path.clone();
+        for path in execs {
+            let path = resolve_exec(&config, &path);
+            if self.documents.iter().any(|d| d.path == path) {
+                continue;
+            }
+            let read = if self.templates {
+                Document::read_or_template(&path).map(|(doc, _)| doc)
+            } else {
+                Document::read(&path)
+            };
+            if let Ok(doc) = read {
+                self.add(doc);
+            }
+        }
+    }
+
+    /// The document at `path`, adding it to the tree if it isn't already in it. A file which
+    /// doesn't exist yet is added empty, and is created when the changes are written.
+    pub fn document_mut(&mut self, path: &str) -> Result<&mut Document, EditError> {
+        if let Some(d) = self.documents.iter().position(|doc| doc.path == path) {
+            return Ok(&mut self.documents[d]);
+        }
+        let doc = match Document::read(path) {
+            Ok(doc) => doc,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Document::parse(path, ""),
+            Err(e) => return Err(EditError::Unreadable(format!("{}: {}", path, e))),
+        };
+        self.originals.push(doc.to_string());
+        self.documents.push(doc);
+        Ok(self.documents.last_mut().unwrap())
+    }
+
+    /// Every statement in the tree with the index of its document and line (both starting at 0),
+    /// in the order they are executed.
+    pub fn statements(&self) -> Vec<(usize, usize, &Statement)> {
+        let mut statements = Vec::new();
+        if !self.documents.is_empty() {
+            self.walk(0, &mut statements, &mut Vec::new());
+        }
+        statements
+    }
+
+    fn walk<'a>(
+        &'a self,
+        d: usize,
+        statements: &mut Vec<(usize, usize, &'a Statement)>,
+        exec_stack: &mut Vec<usize>,
+    ) {
+        exec_stack.push(d);
+        for (l, line) in self.documents[d].lines.iter().enumerate() {
+            if let Some(statement) = line.item.statement() {
+                statements.push((d, l, statement));
+            }
+            if let Item::Exec(statement) = &line.item {
+                let path = resolve_exec(
+                    &self.documents[0].path,
+                    &statement.arg(0).unwrap_or_default(),
+                );
+                if let Some(included) = self.documents.iter().position(|doc| doc.path == path) {
+                    if !exec_stack.contains(&included) {
+                        self.walk(included, statements, exec_stack);
+                    }
+                }
+            }
+        }
+        exec_stack.pop();
+    }
+
+    /// Finds every line which runs one of `commands` with `name` as its first argument.
+    fn find(&self, commands: &[&str], name: &str) -> Vec<(usize, usize)> {
+        self.statements()
+            .into_iter()
+            .filter(|(_, _, s)| runs(s, commands) && s.arg(0).as_deref() == Some(name))
+            .map(|(d, l, _)| (d, l))
+            .collect()
+    }
+
+    /// Finds every line which sets the convar `name`, either with `set` and the like or with
+    /// the command of the same name (such as `sv_maxclients 48`), along with the index of the
+    /// argument holding the value.
+    fn find_convar(&self, name: &str) -> Vec<(usize, usize, usize)> {
+        self.statements()
+            .into_iter()
+            .filter_map(|(d, l, s)| match setting(s) {
+                Some((convar, index)) if convar.eq_ignore_ascii_case(name) => Some((d, l, index)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Finds the last line which runs one of `commands`.
+    fn find_last(&self, commands: &[&str]) -> Option<(usize, usize)> {
+        self.statements()
+            .into_iter()
+            .rev()
+            .find(|(_, _, s)| runs(s, commands))
+            .map(|(d, l, _)| (d, l))
+    }
+
+    /// Inserts a statement after the given line, copying its indentation, or at the end of the
+    /// main file if there is no such line.
+    fn insert_after(&mut self, at: Option<(usize, usize)>, mut statement: Statement) {
+        match at {
+            Some((d, l)) => {
+                let doc = &mut self.documents[d];
+                if let Some(indent) = doc.lines[l]
+                    .item
+                    .statement()
+                    .and_then(|s| s.tokens.first())
+                    .map(|t| t.leading.clone())
+                {
+                    statement.tokens[0].leading = indent;
+                }
+                doc.insert_after(Some(l), Item::Statement(statement));
+            }
+            None => self.documents[0].push(Item::Statement(statement)),
+        }
+    }
+
+    /// Sets a convar. If the convar is already defined, the definition which takes effect is
+    /// changed in place. Otherwise it is added after the last convar in the config.
+    ///
+    /// If `scope` is `None`, an existing definition keeps its scope and a new definition is
+    /// only visible to the server. Names and values containing a quote or a newline are refused.
+    pub fn set_convar(
+        &mut self,
+        name: &str,
+        value: &str,
+        scope: Option<ConvarScope>,
+    ) -> Result<(), EditError> {
+        check_quotable(&[name, value])?;
+        if let Some(&(d, l, mut index)) = self.find_convar(name).last() {
+            let statement = self.documents[d].lines[l].item.statement_mut().unwrap();
+            if let Some(scope) = scope {
+                if index == 0 {
+                    // A setting such as `sv_maxclients 48` becomes `set sv_maxclients 48`
+                    let name = Token {
+                        leading: " ".to_string(),
+                        raw: statement.tokens[0].raw.clone(),
+                    };
+                    statement.tokens.insert(1, name);
+                    statement.tokens[0].raw = scope.command().to_string();
+                    index = 1;
+                } else if ConvarScope::from_command(&statement.command()) != Some(scope) {
+                    statement.tokens[0].raw = scope.command().to_string();
+                }
+            }
+            statement.set_arg(index, value);
+        } else {
+            let at = self.find_last(&CONVAR_COMMANDS);
+            let command = scope.unwrap_or(ConvarScope::Server).command();
+            self.insert_after(at, Statement::new(command, &[name, value]));
+        }
+        Ok(())
+    }
+
+    /// Removes every definition of a convar.
+    pub fn unset_convar(&mut self, name: &str) -> Result<(), EditError> {
+        let found = self.find_convar(name);
+        if found.is_empty() {
+            return Err(EditError::ConvarNotFound(name.to_string()));
+        }
+        self.remove_lines(found.into_iter().map(|(d, l, _)| (d, l)).collect());
+        Ok(())
+    }
+
+    /// Adds an `ensure` for a resource. If `after` is given, it is added directly after the
+    /// line starting that resource. Otherwise it is added after the last resource started.
+    pub fn add_ensure(&mut self, resource: &str, after: Option<&str>) -> Result<(), EditError> {
+        check_quotable(&[resource])?;
+        if !self.find(&START_COMMANDS, resource).is_empty() {
+            return Err(EditError::ResourceAlreadyStarted(resource.to_string()));
+        }
+        let at = match after {
+            Some(after) => Some(
+                *self
+                    .find(&START_COMMANDS, after)
+                    .last()
+                    .ok_or_else(|| EditError::ResourceNotFound(after.to_string()))?,
+            ),
+            None => self.find_last(&START_COMMANDS),
+        };
+        self.insert_after(at, Statement::new("ensure", &[resource]));
+        Ok(())
+    }
+
+    /// Removes every line which starts (with `ensure` or `start`) a resource.
+    pub fn remove_ensure(&mut self, resource: &str) -> Result<(), EditError> {
+        let found = self.find(&START_COMMANDS, resource);
+        if found.is_empty() {
+            return Err(EditError::ResourceNotFound(resource.to_string()));
+        }
+        self.remove_lines(found);
+        Ok(())
+    }
+
+    fn remove_lines(&mut self, mut lines: Vec<(usize, usize)>) {
+        // Remove from the bottom up so indexes stay valid
+        lines.sort_unstable();
+        lines.dedup();
+        for (d, l) in lines.into_iter().rev() {
+            self.documents[d].remove(l);
+        }
+    }
+
+    /// Every document which has been changed since it was loaded, alongside its original text.
+    pub fn changes(&self) -> Vec<(&Document, &str)> {
+        self.documents
+            .iter()
+            .zip(self.originals.iter())
+            .filter(|(doc, original)| doc.to_string() != **original)
+            .map(|(doc, original)| (doc, original.as_str()))
+            .collect()
+    }
+
+    /// Writes every changed document back to its file.
+    pub fn write_changes(&mut self) -> io::Result<()> {
+        for (doc, original) in self.documents.iter().zip(self.originals.iter_mut()) {
+            let text = doc.to_string();
+            if text != *original {
+                doc.write()?;
+                *original = text;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Refuses values which can't be written in a config, rather than losing their quotes.
+fn check_quotable(values: &[&str]) -> Result<(), EditError> {
+    match values.iter().find(|value| !quotable(value)) {
+        Some(value) => Err(EditError::Unquotable(value.to_string())),
+        None => Ok(()),
+    }
+}
+
+/// Whether a statement runs one of `commands`, which are matched without regard to case as the
+/// server does.
+fn runs(statement: &Statement, commands: &[&str]) -> bool {
+    let command = statement.command();
+    commands.iter().any(|c| c.eq_ignore_ascii_case(&command))
+}
+
+/// A single line of a diff.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Change<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Compares two texts line by line, using the longest common subsequence of lines.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Change<'a>> {
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            changes.push(Change::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            changes.push(Change::Removed(old[i]));
+            i += 1;
+        } else {
+            changes.push(Change::Added(new[j]));
+            j += 1;
+        }
+    }
+    changes
+}
+
+/// Produces a unified diff (as used by `diff -u` and `git diff`) between two versions of the
+/// file at `path`, with three lines of context around each change. An empty string is returned
+/// if the texts are the same.
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    const CONTEXT: usize = 3;
+
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let changes = diff_lines(&old_lines, &new_lines);
+    if changes.iter().all(|c| matches!(c, Change::Same(_))) {
+        return String::new();
+    }
+
+    let mut out = format!("--- a/{}\n+++ b/{}\n", path, path);
+    let mut i = 0;
+    while i < changes.len() {
+        // Find the next change, then extend the hunk until there is a long enough gap
+        let Some(first) = changes[i..]
+            .iter()
+            .position(|c| !matches!(c, Change::Same(_)))
+            .map(|p| p + i)
+        else {
+            break;
+        };
+        let start = first.saturating_sub(CONTEXT);
+        let mut end = first;
+        let mut same_run = 0;
+        for (k, change) in changes.iter().enumerate().skip(first) {
+            if matches!(change, Change::Same(_)) {
+                same_run += 1;
+                if same_run > CONTEXT * 2 {
+                    break;
+                }
+            } else {
+                same_run = 0;
+                end = k;
+            }
+        }
+        let end = (end + CONTEXT + 1).min(changes.len());
+
+        // Work out the line numbers the hunk starts at in each file
+        let (mut old_start, mut new_start) = (1, 1);
+        for change in &changes[..start] {
+            match change {
+                Change::Same(_) => {
+                    old_start += 1;
+                    new_start += 1;
+                }
+                Change::Removed(_) => old_start += 1,
+                Change::Added(_) => new_start += 1,
+            }
+        }
+        let hunk = &changes[start..end];
+        let old_len = hunk
+            .iter()
+            .filter(|c| !matches!(c, Change::Added(_)))
+            .count();
+        let new_len = hunk
+            .iter()
+            .filter(|c| !matches!(c, Change::Removed(_)))
+            .count();
+        // An empty range starts at the line before it, by convention
+        if old_len == 0 {
+            old_start -= 1;
+        }
+        if new_len == 0 {
+            new_start -= 1;
+        }
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start, old_len, new_start, new_len
+        ));
+        for change in hunk {
+            match change {
+                Change::Same(line) => out.push_str(&format!(" {}\n", line)),
+                Change::Removed(line) => out.push_str(&format!("-{}\n", line)),
+                Change::Added(line) => out.push_str(&format!("+{}\n", line)),
+            }
+        }
+        i = end;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use std::fs;
+
+    #[test]
+    fn edits_the_file_each_line_belongs_in() {
+        let dir = temp_dir("edit");
+        let config = dir.join("server.cfg");
+        fs::write(
+            &config,
+            "sv_maxclients 32\nset onesync on\nexec resources.cfg\nensure last\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("resources.cfg"),
+            "  ensure chat\nSTART map\nsets tags \"rp\"\n",
+        )
+        .unwrap();
+        let mut tree = ConfigTree::load(&config.to_string_lossy()).unwrap();
+        assert_eq!(tree.documents.len(), 2);
+
+        // Settings written as their own command are changed in place, not added again
+        tree.set_convar("sv_maxclients", "48", None).unwrap();
+        tree.set_convar("SV_MAXCLIENTS", "64", None).unwrap();
+        tree.set_convar("tags", "rp,economy", Some(ConvarScope::Replicated))
+            .unwrap();
+        tree.set_convar("new", "1", None).unwrap();
+        tree.unset_convar("onesync").unwrap();
+        assert_eq!(
+            tree.unset_convar("missing"),
+            Err(EditError::ConvarNotFound("missing".to_string()))
+        );
+        tree.add_ensure("police", Some("chat")).unwrap();
+        assert_eq!(
+            tree.add_ensure("map", None),
+            Err(EditError::ResourceAlreadyStarted("map".to_string()))
+        );
+        assert_eq!(
+            tree.add_ensure("x", Some("nope")),
+            Err(EditError::ResourceNotFound("nope".to_string()))
+        );
+        tree.add_ensure("jobs", None).unwrap();
+        tree.remove_ensure("map").unwrap();
+
+        assert_eq!(
+            tree.documents[0].to_string(),
+            "sv_maxclients 64\nexec resources.cfg\nensure last\nensure jobs\n"
+        );
+        assert_eq!(
+            tree.documents[1].to_string(),
+            "  ensure chat\n  ensure police\nsetr tags rp,economy\nset new 1\n"
+        );
+        assert_eq!(tree.changes().len(), 2);
+
+        tree.set_convar("sv_maxclients", "64", Some(ConvarScope::ServerInfo))
+            .unwrap();
+        assert!(tree.documents[0]
+            .to_string()
+            .starts_with("sets sv_maxclients 64\n"));
+
+        tree.write_changes().unwrap();
+        assert!(tree.changes().is_empty());
+        let reloaded = ConfigTree::load(&config.to_string_lossy()).unwrap();
+        assert_eq!(
+            reloaded.documents[1].to_string(),
+            tree.documents[1].to_string()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_what_follows_the_value() {
+        let doc = Document::parse(
+            "server.cfg",
+            "set b \"x y\" // note\nsv_maxclients 32 # max\n",
+        );
+        let mut tree = ConfigTree::from_document(doc);
+        tree.set_convar("b", "z", None).unwrap();
+        tree.set_convar("sv_maxclients", "48", Some(ConvarScope::Server))
+            .unwrap();
+        assert_eq!(
+            tree.documents[0].to_string(),
+            "set b z // note\nset sv_maxclients 48 # max\n"
+        );
+    }
+
+    #[test]
+    fn quotes_values_the_server_would_split() {
+        let dir = temp_dir("edit-quoting");
+        let config = dir.join("server.cfg").to_string_lossy().into_owned();
+        fs::write(&config, "set mysql_connection_string x\n").unwrap();
+        let mut tree = ConfigTree::load(&config).unwrap();
+        tree.set_convar("mysql_connection_string", "server=db;uid=x", None)
+            .unwrap();
+        tree.set_convar("motd", "#1 server", None).unwrap();
+        tree.set_convar("url", "//cdn", None).unwrap();
+        tree.write_changes().unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&config).unwrap(),
+            "set mysql_connection_string \"server=db;uid=x\"\nset motd \"#1 server\"\n\
+             set url \"//cdn\"\n"
+        );
+        let cfg = crate::config::read_config_file(&config).unwrap();
+        let values: Vec<&str> = cfg.convars.iter().map(|c| c.value.as_str()).collect();
+        assert_eq!(values, vec!["server=db;uid=x", "#1 server", "//cdn"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_values_with_quotes() {
+        let mut tree = ConfigTree::from_document(Document::parse("server.cfg", "set b x\n"));
+        assert_eq!(
+            tree.set_convar("b", "say \"hi\"", None),
+            Err(EditError::Unquotable("say \"hi\"".to_string()))
+        );
+        assert!(tree.set_convar("b", "two\nlines", None).is_err());
+        assert!(tree.add_ensure("a\"b", None).is_err());
+        assert!(tree.changes().is_empty());
+    }
+
+    #[test]
+    fn writes_unified_diffs() {
+        assert_eq!(unified_diff("server.cfg", "a\nb\n", "a\nb\n"), "");
+        assert_eq!(
+            unified_diff(
+                "server.cfg",
+                "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n",
+                "1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n12\n"
+            ),
+            "--- a/server.cfg\n+++ b/server.cfg\n\
+             @@ -1,6 +1,6 @@\n 1\n 2\n-3\n+three\n 4\n 5\n 6\n\
+             @@ -8,5 +8,4 @@\n 8\n 9\n 10\n-11\n 12\n"
+        );
+        assert_eq!(
+            unified_diff("new.cfg", "", "set a 1\n"),
+            "--- a/new.cfg\n+++ b/new.cfg\n@@ -0,0 +1,1 @@\n+set a 1\n"
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use super::document::{quote, Document, Item, Line, Statement, Token};
+use super::setting;
+
+/// The sections statements are grouped into, in the order they are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Section {
+    /// `endpoint_add_tcp` and `endpoint_add_udp`.
+    Endpoints,
+    /// `set`, `sets`, `setr`, `seta` and commands which set the convar of the same name.
+    Convars,
+    /// `ensure`, `start`, `stop`, `restart` and `refresh`.
+    Resources,
+    /// `add_ace`, `remove_ace`, `add_principal` and `remove_principal`.
+    Permissions,
+    /// Any other command.
+    Other,
+}
+
+impl Section {
+    /// The section a statement belongs in, or `None` for `exec`.
+    fn of(statement: &Statement) -> Option<Self> {
+        let command = statement.command().to_lowercase();
+        Some(match command.as_str() {
+            "exec" => return None,
+            "endpoint_add_tcp" | "endpoint_add_udp" => Section::Endpoints,
+            "ensure" | "start" | "stop" | "restart" | "refresh" => Section::Resources,
+            "add_ace" | "remove_ace" | "add_principal" | "remove_principal" => Section::Permissions,
+            _ if setting(statement).is_some() => Section::Convars,
+            _ => Section::Other,
+        })
+    }
+}
+
+/// How a config is formatted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// Sort convars by name. Convars set more than once keep their order, so the same
+    /// definition still wins.
+    pub sort_convars: bool,
+}
+
+/// A statement along with the comments written above it.
+struct Entry {
+    comments: Vec<String>,
+    statement: String,
+    /// Whether the statement was separated from the one before it by a blank line.
+    spaced: bool,
+    section: Option<Section>,
+    /// The convar the statement sets, used for sorting.
+    convar: Option<String>,
+}
+
+impl Entry {
+    /// Whether statements can't be moved past this one. An `exec` can run anything, and
+    /// resources read convars and permissions when they start, so moving a statement past
+    /// either would change what the config does.
+    fn is_barrier(&self) -> bool {
+        matches!(self.section, None | Some(Section::Resources))
+    }
+}
+
+/// Whether a token starts a comment at the end of a line, which runs to the end of the line.
+fn starts_comment(token: &Token) -> bool {
+    token.raw.starts_with('#') || token.raw.starts_with("//")
+}
+
+/// Removes quotes a token doesn't need. Only quoted tokens are changed: quoting an unquoted
+/// token would change what it does, such as making a `;` part of an argument rather than
+/// separating commands. Quotes are kept around values a server could read differently without
+/// them (see [`quote`]), and tokens with quotes inside them are written as they are, as the
+/// quotes can't be escaped.
+fn canonical_token(token: &Token) -> String {
+    let value = token.value();
+    if !token.raw.starts_with('"') || value.contains('"') {
+        token.raw.clone()
+    } else {
+        quote(&value)
+    }
+}
+
+/// Writes a statement with single spaces between its tokens, quoted only where needed. A
+/// comment at the end of the line is written back as it was.
+fn canonical_statement(statement: &Statement) -> String {
+    let end = statement
+        .tokens
+        .iter()
+        .position(starts_comment)
+        .unwrap_or(statement.tokens.len());
+    let mut text = statement.tokens[..end]
+        .iter()
+        .map(canonical_token)
+        .collect::<Vec<_>>()
+        .join(" ");
+    if end < statement.tokens.len() {
+        let comment: String = statement.tokens[end..]
+            .iter()
+            .map(|token| format!("{}{}", token.leading, token.raw))
+            .collect();
+        text.push(' ');
+        text.push_str(comment.trim_start());
+    }
+    text
+}
+
+/// Formats a config file. Statements are written with single spaces between their tokens and
+/// quoted only where needed, and are grouped into sections: endpoints, convars, permissions and
+/// then anything else, separated by blank lines. Statements keep their order within each
+/// section, and are never moved past an `exec` or a command starting or stopping resources, so
+/// the config does the same thing once it is formatted. Runs of resource commands are kept
+/// together as a section of their own. Comments above a statement move with it, and comments at
+/// the start and end of the file stay there.
+pub fn format_document(doc: &Document, options: &FormatOptions) -> Document {
+    let mut header: Vec<String> = Vec::new();
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut comments: Vec<String> = Vec::new();
+    let mut spaced = false;
+    for line in &doc.lines {
+        match &line.item {
+            Item::Blank(_) => {
+                if entries.is_empty() {
+                    // Comments at the start of the file which aren't above a statement
+                    if !header.is_empty() && !comments.is_empty() {
+                        header.push(String::new());
+                    }
+                    header.append(&mut comments);
+                }
+                spaced = true;
+            }
+            Item::Comment(text) => comments.push(text.trim().to_string()),
+            Item::Exec(statement) | Item::Statement(statement) => entries.push(Entry {
+                comments: std::mem::take(&mut comments),
+                statement: canonical_statement(statement),
+                spaced: std::mem::take(&mut spaced),
+                section: Section::of(statement),
+                convar: setting(statement).map(|(name, _)| name.to_lowercase()),
+            }),
+        }
+    }
+    let footer = comments;
+
+    // Each run of statements between barriers is split into sections, and each run of the same
+    // kind of barrier is a group of its own
+    let mut groups: Vec<Vec<Entry>> = Vec::new();
+    let mut run: Vec<Entry> = Vec::new();
+    let flush = |run: &mut Vec<Entry>, groups: &mut Vec<Vec<Entry>>| {
+        // Sorting is stable, so statements keep their order within each section
+        run.sort_by_key(|entry| {
+            let convar = entry.convar.clone().filter(|_| options.sort_convars);
+            (entry.section, convar)
+        });
+        for entry in run.drain(..) {
+            match groups.last_mut() {
+                Some(group) if group[0].section == entry.section => group.push(entry),
+                _ => groups.push(vec![entry]),
+            }
+        }
+    };
+    for entry in entries {
+        if entry.is_barrier() {
+            flush(&mut run, &mut groups);
+        }
+        run.push(entry);
+        if run[0].is_barrier() {
+            flush(&mut run, &mut groups);
+        }
+    }
+    flush(&mut run, &mut groups);
+
+    let mut text: Vec<String> = Vec::new();
+    let mut block = |lines: Vec<String>| {
+        if !text.is_empty() && !lines.is_empty() {
+            text.push(String::new());
+        }
+        text.extend(lines);
+    };
+    block(header);
+    for group in groups {
+        let mut lines = Vec::new();
+        for (i, entry) in group.into_iter().enumerate() {
+            let sorted = options.sort_convars && entry.section == Some(Section::Convars);
+            if entry.spaced && i > 0 && !sorted {
+                lines.push(String::new());
+            }
+            lines.extend(entry.comments);
+            lines.push(entry.statement);
+        }
+        block(lines);
+    }
+    block(footer);
+
+    let newline = doc.newline().to_string();
+    Document {
+        path: doc.path.clone(),
+        lines: text
+            .iter()
+            .map(|line| Line {
+                item: Item::parse(line),
+                ending: newline.clone(),
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_statements_into_sections() {
+        let doc = Document::parse(
+            "server.cfg",
+            "# My server\n\n  ensure   chat  \nadd_ace group.admin command allow\n\
+             # The name shown in the server list\nsv_hostname   \"Test\"\n\n\n\
+             set \"onesync\" on\nendpoint_add_tcp \"0.0.0.0:30120\"\nexec \"secrets.cfg\"\n\
+             set b 1\nsetr a \"x;y\"\n# The end\n",
+        );
+        let expected = "# My server\n\n\
+                        ensure chat\n\n\
+                        endpoint_add_tcp 0.0.0.0:30120\n\n\
+                        # The name shown in the server list\nsv_hostname Test\n\n\
+                        set onesync on\n\n\
+                        add_ace group.admin command allow\n\n\
+                        exec secrets.cfg\n\n\
+                        set b 1\nsetr a \"x;y\"\n\n\
+                        # The end\n";
+        let formatted = format_document(&doc, &FormatOptions::default());
+        assert_eq!(formatted.to_string(), expected);
+        assert_eq!(
+            format_document(&formatted, &FormatOptions::default()).to_string(),
+            expected
+        );
+
+        let sorted = format_document(&doc, &FormatOptions { sort_convars: true });
+        assert!(sorted
+            .to_string()
+            .contains("set onesync on\n# The name shown in the server list\nsv_hostname Test\n"));
+        assert!(sorted.to_string().contains("setr a \"x;y\"\nset b 1\n"));
+    }
+
+    #[test]
+    fn trailing_comments_are_kept_as_comments() {
+        let doc = Document::parse(
+            "server.cfg",
+            "ensure   chat // core\nset x \"1\"  # a  \"note\"\nset y a;b\nset z \"a;b\"\n",
+        );
+        assert_eq!(
+            format_document(&doc, &FormatOptions::default()).to_string(),
+            "ensure chat // core\n\nset x 1 # a  \"note\"\nset y a;b\nset z \"a;b\"\n"
+        );
+
+        // Values with quotes inside them can't be requoted without losing them
+        let doc = Document::parse("server.cfg", "set  q   \"say \"hi\"\"\n");
+        assert_eq!(
+            format_document(&doc, &FormatOptions::default()).to_string(),
+            "set q \"say \"hi\"\"\n"
+        );
+    }
+
+    #[test]
+    fn statements_are_not_moved_past_resources() {
+        let doc = Document::parse(
+            "server.cfg",
+            "ensure a\nset x 1\nset w 0\nensure b\nstart c\nadd_ace a b allow\nset y 2\n",
+        );
+        assert_eq!(
+            format_document(&doc, &FormatOptions { sort_convars: true }).to_string(),
+            "ensure a\n\nset w 0\nset x 1\n\nensure b\nstart c\n\nset y 2\n\nadd_ace a b allow\n"
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use std::fmt;
+
+use serde::Serialize;
+
+/// A command which changes which resources are running.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ResourceCommand {
+    /// `start`: starts a resource which isn't running.
+    Start,
+    /// `ensure`: starts a resource, or restarts it if it is already running.
+    Ensure,
+    /// `stop`: stops a running resource.
+    Stop,
+    /// `restart`: stops and starts a resource which is running.
+    Restart,
+    /// `refresh`: rescans the resources folder. This doesn't change what is running.
+    Refresh,
+}
+
+impl ResourceCommand {
+    /// Gets the resource command a config command runs, if it is one.
+    pub fn from_command(command: &str) -> Option<Self> {
+        match command {
+            "start" => Some(ResourceCommand::Start),
+            "ensure" => Some(ResourceCommand::Ensure),
+            "stop" => Some(ResourceCommand::Stop),
+            "restart" => Some(ResourceCommand::Restart),
+            "refresh" => Some(ResourceCommand::Refresh),
+            _ => None,
+        }
+    }
+
+    /// Whether the command needs the name of a resource.
+    pub fn takes_resource(&self) -> bool {
+        !matches!(self, ResourceCommand::Refresh)
+    }
+}
+
+impl fmt::Display for ResourceCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let command = match self {
+            ResourceCommand::Start => "start",
+            ResourceCommand::Ensure => "ensure",
+            ResourceCommand::Stop => "stop",
+            ResourceCommand::Restart => "restart",
+            ResourceCommand::Refresh => "refresh",
+        };
+        write!(f, "{}", command)
+    }
+}
+
+/// A resource which is running, and where it was started.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+pub struct StartedResource {
+    /// The name of the resource.
+    pub name: String,
+    /// The path of the file the resource was started in.
+    pub file: String,
+    /// The line the resource was started on, starting at 1.
+    pub line: usize,
+}
+
+/// Replays resource commands in the order they are run, keeping track of which resources are
+/// running and the order they were started in.
+#[derive(Clone, Debug, Default)]
+pub struct ResourceSimulator {
+    running: Vec<StartedResource>,
+    /// Every resource which has been stopped at some point, so later stops can be explained.
+    stopped: Vec<String>,
+}
+
+impl ResourceSimulator {
+    /// Creates a simulator with no resources running.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The resources running, in the order they were started. A restarted resource keeps its
+    /// place, as anything started after it has already been started with it running.
+    pub fn running(&self) -> &[StartedResource] {
+        &self.running
+    }
+
+    /// Consumes the simulator, giving the resources running in the order they were started.
+    pub fn into_running(self) -> Vec<StartedResource> {
+        self.running
+    }
+
+    fn is_running(&self, name: &str) -> bool {
+        self.running.iter().any(|r| r.name == name)
+    }
+
+    /// Applies a command to a resource, found in `file` on `line`. If the command probably
+    /// isn't doing what was intended, a warning explaining why is returned.
+    pub fn apply(
+        &mut self,
+        command: ResourceCommand,
+        name: &str,
+        file: &str,
+        line: usize,
+    ) -> Option<String> {
+        let started = StartedResource {
+            name: name.to_string(),
+            file: file.to_string(),
+            line,
+        };
+        match command {
+            ResourceCommand::Start => {
+                if let Some(previous) = self.running.iter().find(|r| r.name == name) {
+                    return Some(format!(
+                        "`{}` is already started (at {}:{}), so this does nothing",
+                        name, previous.file, previous.line
+                    ));
+                }
+                self.running.push(started);
+            }
+            ResourceCommand::Ensure => {
+                if let Some(previous) = self.running.iter().find(|r| r.name == name) {
+                    return Some(format!(
+                        "`{}` is already started (at {}:{}), so this restarts it",
+                        name, previous.file, previous.line
+                    ));
+                }
+                self.running.push(started);
+            }
+            ResourceCommand::Stop => {
+                if !self.is_running(name) {
+                    return Some(if self.stopped.iter().any(|s| s == name) {
+                        format!("`{}` is already stopped, so this does nothing", name)
+                    } else {
+                        format!(
+                            "`{}` is stopped before it has been started, so this does nothing",
+                            name
+                        )
+                    });
+                }
+                self.running.retain(|r| r.name != name);
+                self.stopped.push(name.to_string());
+            }
+            ResourceCommand::Restart => {
+                // The server ignores restarting a resource which isn't running
+                if !self.is_running(name) {
+                    return Some(format!(
+                        "`{}` isn't running, so this does nothing; use `ensure` to start it",
+                        name
+                    ));
+                }
+            }
+            ResourceCommand::Refresh => (),
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(commands: &[(ResourceCommand, &str)]) -> (Vec<String>, Vec<Option<String>>) {
+        let mut simulator = ResourceSimulator::new();
+        let warnings = commands
+            .iter()
+            .enumerate()
+            .map(|(i, (command, name))| simulator.apply(*command, name, "server.cfg", i + 1))
+            .collect();
+        let running = simulator.running().iter().map(|r| r.name.clone()).collect();
+        (running, warnings)
+    }
+
+    #[test]
+    fn replays_resource_commands() {
+        use ResourceCommand::*;
+
+        let (running, warnings) = run(&[
+            (Start, "a"),
+            (Start, "a"),
+            (Ensure, "b"),
+            (Ensure, "b"),
+            (Restart, "a"),
+            (Refresh, ""),
+        ]);
+        assert_eq!(running, vec!["a", "b"]);
+        assert_eq!(
+            warnings,
+            vec![
+                None,
+                Some("`a` is already started (at server.cfg:1), so this does nothing".to_string()),
+                None,
+                Some("`b` is already started (at server.cfg:3), so this restarts it".to_string()),
+                None,
+                None,
+            ]
+        );
+
+        let (running, warnings) = run(&[
+            (Stop, "a"),
+            (Restart, "b"),
+            (Ensure, "a"),
+            (Stop, "a"),
+            (Stop, "a"),
+            (Start, "a"),
+        ]);
+        assert_eq!(running, vec!["a"]);
+        assert_eq!(
+            warnings,
+            vec![
+                Some("`a` is stopped before it has been started, so this does nothing".to_string()),
+                Some(
+                    "`b` isn't running, so this does nothing; use `ensure` to start it".to_string()
+                ),
+                None,
+                None,
+                Some("`a` is already stopped, so this does nothing".to_string()),
+                None,
+            ]
+        );
+
+        assert_eq!(ResourceCommand::from_command("ensure"), Some(Ensure));
+        assert_eq!(ResourceCommand::from_command("exec"), None);
+    }
+}
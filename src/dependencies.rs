@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::config::{Diagnostic, Severity, StartedResource};
+use crate::manifest::ResourceManifest;
+
+/// A dependency which can't be found in the resources folder.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MissingDependency {
+    /// The resource with the dependency.
+    pub resource: StartedResource,
+    /// The name of the dependency.
+    pub dependency: String,
+}
+
+/// A dependency which is started after the resource which needs it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct LateDependency {
+    /// The resource with the dependency.
+    pub resource: StartedResource,
+    /// The name of the dependency, as written in the manifest.
+    pub dependency: String,
+    /// Where the resource satisfying the dependency is started.
+    pub started: StartedResource,
+}
+
+/// The result of checking the order resources are started in against their dependencies.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct DependencyReport {
+    /// Dependencies which aren't in the resources folder, or provided by any resource in it.
+    pub missing: Vec<MissingDependency>,
+    /// Dependencies which are started after the resources which need them.
+    pub late: Vec<LateDependency>,
+    /// Groups of started resources which depend on each other in a loop.
+    pub cycles: Vec<Vec<StartedResource>>,
+    /// An order to start the resources in which satisfies every dependency, keeping as close
+    /// to the existing order as possible. Resources in cycles are left in their existing order.
+    pub suggested_order: Vec<String>,
+}
+
+impl DependencyReport {
+    /// Whether no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.late.is_empty() && self.cycles.is_empty()
+    }
+
+    /// Describes each problem found as a diagnostic, pointing at where the resource is started.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let at = |resource: &StartedResource, severity, message| Diagnostic {
+            severity,
+            message,
+            file: resource.file.clone(),
+            line: resource.line,
+            column: 1,
+            directive: None,
+            snippet: None,
+        };
+
+        let mut diagnostics = Vec::new();
+        for missing in &self.missing {
+            diagnostics.push(at(
+                &missing.resource,
+                Severity::Error,
+                format!(
+                    "`{}` depends on `{}`, which isn't in the resources folder",
+                    missing.resource.name, missing.dependency
+                ),
+            ));
+        }
+        for late in &self.late {
+            diagnostics.push(at(
+                &late.resource,
+                Severity::Warning,
+                format!(
+                    "`{}` depends on `{}`, which isn't started until {}:{}",
+                    late.resource.name, late.dependency, late.started.file, late.started.line
+                ),
+            ));
+        }
+        for cycle in &self.cycles {
+            let mut chain: Vec<&str> = cycle.iter().map(|r| r.name.as_str()).collect();
+            chain.push(&cycle[0].name);
+            diagnostics.push(at(
+                &cycle[0],
+                Severity::Error,
+                format!(
+                    "resources depend on each other in a cycle: {}",
+                    chain.join(" -> ")
+                ),
+            ));
+        }
+        diagnostics
+    }
+}
+
+/// Finds the resource which satisfies a dependency, either by having its name or by
+/// `provide`ing it. Resources which are started are preferred.
+pub fn resolve<'a>(
+    dependency: &str,
+    started: &'a [StartedResource],
+    manifests: &'a HashMap<String, ResourceManifest>,
+) -> Option<&'a str> {
+    let provides = |name: &str| {
+        name == dependency
+            || manifests
+                .get(name)
+                .map(|m| m.provides.iter().any(|p| p == dependency))
+                .unwrap_or(false)
+    };
+    if let Some(res) = started.iter().find(|r| provides(&r.name)) {
+        return Some(&res.name);
+    }
+    if let Some((name, _)) = manifests.get_key_value(dependency) {
+        return Some(name);
+    }
+    let mut providers: Vec<&String> = manifests.keys().filter(|name| provides(name)).collect();
+    providers.sort();
+    providers.first().map(|name| name.as_str())
+}
+
+/// Checks that every started resource's dependencies exist and are started before it,
+/// and suggests an order which satisfies them. `manifests` should contain the manifest of
+/// every resource in the resources folder.
+pub fn check_start_order(
+    started: &[StartedResource],
+    manifests: &HashMap<String, ResourceManifest>,
+) -> DependencyReport {
+    let mut report = DependencyReport::default();
+    let position = |name: &str| started.iter().position(|r| r.name == name);
+
+    // The started resources each started resource depends on
+    let mut edges: Vec<Vec<usize>> = vec![Vec::new(); started.len()];
+    for (i, resource) in started.iter().enumerate() {
+        let manifest = match manifests.get(&resource.name) {
+            Some(manifest) => manifest,
+            None => continue,
+        };
+        for dependency in manifest.resource_dependencies() {
+            match resolve(dependency, started, manifests) {
+                None => report.missing.push(MissingDependency {
+                    resource: resource.clone(),
+                    dependency: dependency.to_string(),
+                }),
+                Some(name) => {
+                    if let Some(j) = position(name) {
+                        edges[i].push(j);
+                        if j > i {
+                            report.late.push(LateDependency {
+                                resource: resource.clone(),
+                                dependency: dependency.to_string(),
+                                started: started[j].clone(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    report.cycles = find_cycles(&edges)
+        .into_iter()
+        .map(|cycle| cycle.into_iter().map(|i| started[i].clone()).collect())
+        .collect();
+
+    // Repeatedly start the earliest resource whose dependencies are all started, ignoring
+    // dependencies within the same cycle as they can't be satisfied
+    let cycle_of: Vec<Option<usize>> = (0..started.len())
+        .map(|i| report.cycles.iter().position(|c| c.contains(&started[i])))
+        .collect();
+    let mut done = vec![false; started.len()];
+    while report.suggested_order.len() < started.len() {
+        let ready = |i: usize| {
+            edges[i]
+                .iter()
+                .all(|&j| done[j] || (cycle_of[i].is_some() && cycle_of[i] == cycle_of[j]))
+        };
+        let next = (0..started.len())
+            .find(|&i| !done[i] && ready(i))
+            .or_else(|| (0..started.len()).find(|&i| !done[i]))
+            .unwrap();
+        done[next] = true;
+        report.suggested_order.push(started[next].name.clone());
+    }
+
+    report
+}
+
+/// Finds every cycle in a graph using Tarjan's strongly connected components algorithm,
+/// including nodes with an edge to themselves. Each cycle is given in the order its nodes first
+/// appear.
+fn find_cycles(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    struct Tarjan<'a> {
+        edges: &'a [Vec<usize>],
+        index: Vec<Option<usize>>,
+        low: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        next: usize,
+        components: Vec<Vec<usize>>,
+    }
+
+    impl Tarjan<'_> {
+        fn visit(&mut self, v: usize) {
+            self.index[v] = Some(self.next);
+            self.low[v] = self.next;
+            self.next += 1;
+            self.stack.push(v);
+            self.on_stack[v] = true;
+
+            for &w in &self.edges[v] {
+                match self.index[w] {
+                    None => {
+                        self.visit(w);
+                        self.low[v] = self.low[v].min(self.low[w]);
+                    }
+                    Some(index) if self.on_stack[w] => self.low[v] = self.low[v].min(index),
+                    _ => (),
+                }
+            }
+
+            if Some(self.low[v]) == self.index[v] {
+                let mut component = Vec::new();
+                while let Some(w) = self.stack.pop() {
+                    self.on_stack[w] = false;
+                    component.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                if component.len() > 1 || self.edges[v].contains(&v) {
+                    component.sort_unstable();
+                    self.components.push(component);
+                }
+            }
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        edges,
+        index: vec![None; edges.len()],
+        low: vec![0; edges.len()],
+        on_stack: vec![false; edges.len()],
+        stack: Vec::new(),
+        next: 0,
+        components: Vec::new(),
+    };
+    for v in 0..edges.len() {
+        if tarjan.index[v].is_none() {
+            tarjan.visit(v);
+        }
+    }
+    tarjan.components.sort();
+    tarjan.components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn started(names: &[&str]) -> Vec<StartedResource> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| StartedResource {
+                name: name.to_string(),
+                file: "server.cfg".to_string(),
+                line: i + 1,
+            })
+            .collect()
+    }
+
+    fn manifests(deps: &[(&str, &[&str], &[&str])]) -> HashMap<String, ResourceManifest> {
+        deps.iter()
+            .map(|(name, dependencies, provides)| {
+                let manifest = ResourceManifest {
+                    dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+                    provides: provides.iter().map(|p| p.to_string()).collect(),
+                    ..Default::default()
+                };
+                (name.to_string(), manifest)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_missing_and_late_dependencies() {
+        let started = started(&["app", "oxmysql", "es_extended", "unstarted_dep"]);
+        let manifests = manifests(&[
+            (
+                "app",
+                &["es_extended", "mysql-async", "ghost", "/onesync"],
+                &[],
+            ),
+            ("oxmysql", &[], &["mysql-async"]),
+            ("es_extended", &["oxmysql"], &[]),
+            ("unstarted_dep", &["spare"], &[]),
+            ("spare", &[], &[]),
+        ]);
+        let report = check_start_order(&started, &manifests);
+
+        let missing: Vec<(&str, &str)> = report
+            .missing
+            .iter()
+            .map(|m| (m.resource.name.as_str(), m.dependency.as_str()))
+            .collect();
+        assert_eq!(missing, vec![("app", "ghost")]);
+        // `mysql-async` is satisfied by `oxmysql`, which provides it
+        let late: Vec<(&str, &str, &str)> = report
+            .late
+            .iter()
+            .map(|l| {
+                let (resource, dependency) = (l.resource.name.as_str(), l.dependency.as_str());
+                (resource, dependency, l.started.name.as_str())
+            })
+            .collect();
+        assert_eq!(
+            late,
+            vec![
+                ("app", "es_extended", "es_extended"),
+                ("app", "mysql-async", "oxmysql"),
+            ]
+        );
+        assert!(report.cycles.is_empty());
+        assert_eq!(
+            report.suggested_order,
+            vec!["oxmysql", "es_extended", "app", "unstarted_dep"]
+        );
+        assert_eq!(report.diagnostics().len(), 3);
+        assert!(!report.is_ok());
+
+        let report = check_start_order(&started[1..3], &manifests);
+        assert!(report.is_ok());
+        assert_eq!(report.suggested_order, vec!["oxmysql", "es_extended"]);
+    }
+
+    #[test]
+    fn finds_cycles() {
+        let started = started(&["a", "b", "c", "d"]);
+        let manifests = manifests(&[
+            ("a", &["b"], &[]),
+            ("b", &["a"], &[]),
+            ("c", &["c"], &[]),
+            ("d", &["a"], &[]),
+        ]);
+        let report = check_start_order(&started, &manifests);
+        let cycles: Vec<Vec<&str>> = report
+            .cycles
+            .iter()
+            .map(|c| c.iter().map(|r| r.name.as_str()).collect())
+            .collect();
+        assert_eq!(cycles, vec![vec!["a", "b"], vec!["c"]]);
+        assert_eq!(report.suggested_order, vec!["a", "b", "c", "d"]);
+        let messages: Vec<String> = report
+            .diagnostics()
+            .into_iter()
+            .map(|d| d.message)
+            .filter(|m| m.contains("cycle"))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "resources depend on each other in a cycle: a -> b -> a",
+                "resources depend on each other in a cycle: c -> c",
+            ]
+        );
+
+        // The same input always gives the same order
+        for _ in 0..5 {
+            assert_eq!(
+                check_start_order(&started, &manifests).suggested_order,
+                report.suggested_order
+            );
+        }
+        assert_eq!(
+            find_cycles(&[vec![1], vec![2], vec![]]),
+            Vec::<Vec<usize>>::new()
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::config::StartedResource;
+use crate::dependencies;
+use crate::manifest::ResourceManifest;
+
+/// Whether a resource in the graph is started by the config and can be found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeState {
+    /// Started by the config and found in the resources folder.
+    Ensured,
+    /// Started by the config or depended on, but not found in the resources folder.
+    Missing,
+    /// Found in the resources folder, but never started by the config.
+    Extra,
+}
+
+impl NodeState {
+    /// The colour used to fill nodes in this state.
+    fn colour(&self) -> &'static str {
+        match self {
+            NodeState::Ensured => "#9be39b",
+            NodeState::Missing => "#f28b82",
+            NodeState::Extra => "#fdd663",
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            NodeState::Ensured => "ensured",
+            NodeState::Missing => "missing",
+            NodeState::Extra => "extra",
+        }
+    }
+}
+
+/// A resource in the graph.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Node {
+    /// The name of the resource.
+    pub name: String,
+    /// Whether the resource is started and can be found.
+    pub state: NodeState,
+    /// The path of the resource, if it was found.
+    pub path: Option<String>,
+}
+
+/// A dependency of one resource on another.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Edge {
+    /// The resource with the dependency.
+    pub from: String,
+    /// The resource satisfying the dependency.
+    pub to: String,
+    /// If the dependency is satisfied by `to` providing it (with `provide`), the name of the
+    /// dependency.
+    pub provides: Option<String>,
+}
+
+/// A graph of resources and the dependencies between them.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ResourceGraph {
+    /// Every resource, starting with those started by the config in the order they are started.
+    pub nodes: Vec<Node>,
+    /// Every dependency between resources.
+    pub edges: Vec<Edge>,
+}
+
+impl ResourceGraph {
+    /// Builds the graph from the resources started by the config, the resources found by
+    /// `detect_resources` and their manifests.
+    pub fn build(
+        started: &[StartedResource],
+        found: &HashMap<String, String>,
+        manifests: &HashMap<String, ResourceManifest>,
+    ) -> Self {
+        let mut graph = Self::default();
+        for res in started {
+            graph.add_node(&res.name, true, found);
+        }
+        let mut extra: Vec<&String> = found.keys().collect();
+        extra.sort();
+        for name in extra {
+            graph.add_node(name, false, found);
+        }
+
+        let mut missing = Vec::new();
+        for node in &graph.nodes {
+            let manifest = match manifests.get(&node.name) {
+                Some(manifest) => manifest,
+                None => continue,
+            };
+            for dependency in manifest.resource_dependencies() {
+                let to = match dependencies::resolve(dependency, started, manifests) {
+                    Some(to) => to.to_string(),
+                    None => {
+                        missing.push(dependency.to_string());
+                        dependency.to_string()
+                    }
+                };
+                let edge = Edge {
+                    from: node.name.clone(),
+                    provides: if to == dependency {
+                        None
+                    } else {
+                        Some(dependency.to_string())
+                    },
+                    to,
+                };
+                if !graph.edges.contains(&edge) {
+                    graph.edges.push(edge);
+                }
+            }
+        }
+        missing.sort();
+        for name in missing {
+            graph.add_node(&name, false, found);
+        }
+
+        graph
+    }
+
+    fn add_node(&mut self, name: &str, started: bool, found: &HashMap<String, String>) {
+        if self.nodes.iter().any(|n| n.name == name) {
+            return;
+        }
+        let state = match (started, found.contains_key(name)) {
+            (_, false) => NodeState::Missing,
+            (true, true) => NodeState::Ensured,
+            (false, true) => NodeState::Extra,
+        };
+        self.nodes.push(Node {
+            name: name.to_string(),
+            state,
+            path: found.get(name).cloned(),
+        });
+    }
+
+    /// Renders the graph in the Graphviz DOT language.
+    pub fn to_dot(&self) -> String {
+        let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut out = String::from("digraph resources {\n");
+        out.push_str("    rankdir=LR;\n");
+        out.push_str("    node [shape=box, style=filled];\n");
+        for node in &self.nodes {
+            out.push_str(&format!(
+                "    \"{}\" [fillcolor=\"{}\", tooltip=\"{}\"];\n",
+                escape(&node.name),
+                node.state.colour(),
+                node.state.name()
+            ));
+        }
+        for edge in &self.edges {
+            match &edge.provides {
+                Some(provides) => out.push_str(&format!(
+                    "    \"{}\" -> \"{}\" [label=\"provides {}\", style=dashed];\n",
+                    escape(&edge.from),
+                    escape(&edge.to),
+                    escape(provides)
+                )),
+                None => out.push_str(&format!(
+                    "    \"{}\" -> \"{}\";\n",
+                    escape(&edge.from),
+                    escape(&edge.to)
+                )),
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Renders the graph as a Mermaid flowchart.
+    pub fn to_mermaid(&self) -> String {
+        let escape = |s: &str| s.replace('"', "#quot;");
+        let id = |name: &str| {
+            format!(
+                "n{}",
+                self.nodes.iter().position(|n| n.name == name).unwrap()
+            )
+        };
+        let mut out = String::from("graph LR\n");
+        for node in &self.nodes {
+            out.push_str(&format!(
+                "    {}[\"{}\"]:::{}\n",
+                id(&node.name),
+                escape(&node.name),
+                node.state.name()
+            ));
+        }
+        for edge in &self.edges {
+            match &edge.provides {
+                Some(provides) => out.push_str(&format!(
+                    "    {} -.->|\"provides {}\"| {}\n",
+                    id(&edge.from),
+                    escape(provides),
+                    id(&edge.to)
+                )),
+                None => out.push_str(&format!("    {} --> {}\n", id(&edge.from), id(&edge.to))),
+            }
+        }
+        for state in [NodeState::Ensured, NodeState::Missing, NodeState::Extra] {
+            out.push_str(&format!(
+                "    classDef {} fill:{},color:#000\n",
+                state.name(),
+                state.colour()
+            ));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph() -> ResourceGraph {
+        let started = vec![
+            StartedResource {
+                name: "app".to_string(),
+                file: "server.cfg".to_string(),
+                line: 1,
+            },
+            StartedResource {
+                name: "oxmysql".to_string(),
+                file: "server.cfg".to_string(),
+                line: 2,
+            },
+        ];
+        let found: HashMap<String, String> = [
+            ("app", "resources/app"),
+            ("oxmysql", "resources/oxmysql"),
+            ("spare", "resources/spare"),
+        ]
+        .iter()
+        .map(|(name, path)| (name.to_string(), path.to_string()))
+        .collect();
+        let mut manifests = HashMap::new();
+        manifests.insert(
+            "app".to_string(),
+            ResourceManifest {
+                dependencies: vec!["mysql-async".to_string(), "gone".to_string()],
+                ..Default::default()
+            },
+        );
+        manifests.insert(
+            "oxmysql".to_string(),
+            ResourceManifest {
+                provides: vec!["mysql-async".to_string()],
+                ..Default::default()
+            },
+        );
+        ResourceGraph::build(&started, &found, &manifests)
+    }
+
+    #[test]
+    fn renders_graphs() {
+        let graph = graph();
+        let states: Vec<(&str, NodeState)> = graph
+            .nodes
+            .iter()
+            .map(|n| (n.name.as_str(), n.state))
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                ("app", NodeState::Ensured),
+                ("oxmysql", NodeState::Ensured),
+                ("spare", NodeState::Extra),
+                ("gone", NodeState::Missing),
+            ]
+        );
+
+        assert_eq!(
+            graph.to_dot(),
+            "digraph resources {\n    rankdir=LR;\n    node [shape=box, style=filled];\n\
+             \x20   \"app\" [fillcolor=\"#9be39b\", tooltip=\"ensured\"];\n\
+             \x20   \"oxmysql\" [fillcolor=\"#9be39b\", tooltip=\"ensured\"];\n\
+             \x20   \"spare\" [fillcolor=\"#fdd663\", tooltip=\"extra\"];\n\
+             \x20   \"gone\" [fillcolor=\"#f28b82\", tooltip=\"missing\"];\n\
+             \x20   \"app\" -> \"oxmysql\" [label=\"provides mysql-async\", style=dashed];\n\
+             \x20   \"app\" -> \"gone\";\n}\n"
+        );
+        assert_eq!(
+            graph.to_mermaid(),
+            "graph LR\n    n0[\"app\"]:::ensured\n    n1[\"oxmysql\"]:::ensured\n\
+             \x20   n2[\"spare\"]:::extra\n    n3[\"gone\"]:::missing\n\
+             \x20   n0 -.->|\"provides mysql-async\"| n1\n    n0 --> n3\n\
+             \x20   classDef ensured fill:#9be39b,color:#000\n\
+             \x20   classDef missing fill:#f28b82,color:#000\n\
+             \x20   classDef extra fill:#fdd663,color:#000\n"
+        );
+
+        // JSON and YAML are printed from the graph as it is serialised
+        let json = serde_json::to_value(&graph).unwrap();
+        assert_eq!(json["nodes"][2]["state"], "extra");
+        assert_eq!(json["nodes"][3]["path"], serde_json::Value::Null);
+        assert_eq!(json["edges"][0]["provides"], "mysql-async");
+        assert_eq!(json["edges"][1]["to"], "gone");
+    }
+}
@@ -0,0 +1,432 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::artifacts::{self, Artifact, ArtifactChannel, ArtifactError, ArtifactServer};
+use crate::integrity::{self, BuildManifest};
+
+/// The kind of archive a build is packaged in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ArchiveFormat {
+    /// An xz-compressed tarball, used for Linux builds.
+    TarXz,
+    /// A zip file, used for Windows builds.
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Works out the format of an archive from its file name.
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        if name.ends_with(".tar.xz") {
+            Some(ArchiveFormat::TarXz)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// The error returned when a build couldn't be installed.
+#[derive(Debug)]
+pub enum InstallError {
+    /// The list of builds couldn't be read from the artifact server.
+    Listing(ArtifactError),
+    /// The version asked for isn't on the artifact server.
+    NotFound(String),
+    /// The artifact server marks the build as broken or revoked.
+    Broken(u16),
+    /// The archive couldn't be downloaded.
+    Http(reqwest::Error),
+    /// The artifact server responded with an error status.
+    Status(u16),
+    /// The download ended before the whole archive was received.
+    Incomplete {
+        /// The size the server said the archive was, in bytes.
+        expected: u64,
+        /// The number of bytes received.
+        received: u64,
+    },
+    /// The archive's SHA-256 checksum isn't the one expected.
+    ChecksumMismatch {
+        /// The checksum expected.
+        expected: String,
+        /// The checksum of the archive downloaded.
+        actual: String,
+    },
+    /// The archive isn't in a format which can be extracted.
+    UnknownFormat(String),
+    /// The archive couldn't be extracted.
+    Extract(String),
+    /// A file couldn't be read or written.
+    Io(io::Error),
+}
+
+impl fmt::Display for InstallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstallError::Listing(e) => write!(f, "{}", e),
+            InstallError::NotFound(version) => {
+                write!(f, "version `{}` isn't on the artifact server", version)
+            }
+            InstallError::Broken(num) => write!(
+                f,
+                "artifact {} is marked as broken or revoked, so won't be installed",
+                num
+            ),
+            InstallError::Http(e) => write!(f, "failed to download artifact: {}", e),
+            InstallError::Status(status) => {
+                write!(f, "the artifact server responded with status {}", status)
+            }
+            InstallError::Incomplete { expected, received } => write!(
+                f,
+                "the download ended early ({} of {} bytes received)",
+                received, expected
+            ),
+            InstallError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "the download's SHA-256 checksum is {}, but {} was expected",
+                actual, expected
+            ),
+            InstallError::UnknownFormat(name) => {
+                write!(f, "don't know how to extract `{}`", name)
+            }
+            InstallError::Extract(e) => write!(f, "failed to extract artifact: {}", e),
+            InstallError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for InstallError {}
+
+impl From<io::Error> for InstallError {
+    fn from(e: io::Error) -> Self {
+        InstallError::Io(e)
+    }
+}
+
+impl From<reqwest::Error> for InstallError {
+    fn from(e: reqwest::Error) -> Self {
+        InstallError::Http(e)
+    }
+}
+
+/// Finds the artifact for a version, which is either an artifact number or one of `latest`
+/// (the newest build), `recommended` or `optional`.
+pub fn resolve_artifact(
+    server: &mut ArtifactServer,
+    version: &str,
+) -> Result<Artifact, InstallError> {
+    server.listing().map_err(InstallError::Listing)?;
+    let not_found = || InstallError::NotFound(version.to_string());
+    if let Ok(channel) = version.parse::<ArtifactChannel>() {
+        return server.channel(channel).ok_or_else(not_found);
+    }
+    let num = version.parse::<u16>().map_err(|_| not_found())?;
+    server.get_artifact(num).ok_or_else(not_found)
+}
+
+/// A build installed by [`install`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Installed {
+    /// The SHA-256 checksum of the build's archive.
+    pub sha256: String,
+    /// Why the download failed from each URL tried before the one it came from.
+    pub warnings: Vec<String>,
+}
+
+/// Downloads the archive of an artifact to a file, trying the artifact server it was listed by
+/// and then each of `mirrors` until one works (see [`Artifact::download_urls`]). Why each URL
+/// tried before the one which worked failed is returned, or the last error if none worked.
+/// `progress` is called as the download goes with the number of bytes received so far and the
+/// total size, if the server gave it.
+pub fn download(
+    artifact: &Artifact,
+    mirrors: &[String],
+    to: &Path,
+    progress: &mut dyn FnMut(u64, Option<u64>),
+) -> Result<Vec<String>, InstallError> {
+    let urls = artifact.download_urls(mirrors);
+    let mut warnings = Vec::new();
+    for (i, url) in urls.iter().enumerate() {
+        match download_from(url, to, progress) {
+            Ok(()) => break,
+            Err(e) if i + 1 < urls.len() => warnings.push(format!("{}: {}", url, e)),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(warnings)
+}
+
+/// How long to wait to connect to the artifact server before giving up on a download.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Creates a client for downloading archives. There is no limit on how long a request can take,
+/// as whole builds take minutes to download even on a good connection.
+fn download_client() -> reqwest::Result<reqwest::blocking::Client> {
+    reqwest::blocking::Client::builder()
+        .user_agent(artifacts::USER_AGENT)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(None)
+        .build()
+}
+
+fn download_from(
+    url: &str,
+    to: &Path,
+    progress: &mut dyn FnMut(u64, Option<u64>),
+) -> Result<(), InstallError> {
+    let (mut response, total): (Box<dyn Read>, Option<u64>) = match artifacts::local_path(url) {
+        Some(path) => {
+            let file = File::open(path)?;
+            let len = file.metadata()?.len();
+            (Box::new(file), Some(len))
+        }
+        None => {
+            let response = download_client()?.get(url).send()?;
+            if !response.status().is_success() {
+                return Err(InstallError::Status(response.status().as_u16()));
+            }
+            let total = response.content_length();
+            (Box::new(response), total)
+        }
+    };
+
+    let mut file = File::create(to)?;
+    let mut buf = vec![0; 64 * 1024];
+    let mut received = 0;
+    progress(received, total);
+    loop {
+        let read = response.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&buf[..read])?;
+        received += read as u64;
+        progress(received, total);
+    }
+    file.flush()?;
+
+    match total {
+        Some(expected) if expected != received => {
+            Err(InstallError::Incomplete { expected, received })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Extracts an archive into a directory, which is created if it doesn't exist.
+pub fn extract(archive: &Path, format: ArchiveFormat, dest: &Path) -> Result<(), InstallError> {
+    fs::create_dir_all(dest)?;
+    match format {
+        ArchiveFormat::TarXz => {
+            // The tarball is decompressed to disk first, as builds are too big to hold in memory
+            let tar_path = archive.with_extension("");
+            let result = (|| {
+                let mut input = BufReader::new(File::open(archive)?);
+                let mut tar = File::create(&tar_path)?;
+                lzma_rs::xz_decompress(&mut input, &mut tar)
+                    .map_err(|e| InstallError::Extract(e.to_string()))?;
+                drop(tar);
+                tar::Archive::new(File::open(&tar_path)?).unpack(dest)?;
+                Ok(())
+            })();
+            let _ = fs::remove_file(&tar_path);
+            result
+        }
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipArchive::new(File::open(archive)?)
+                .map_err(|e| InstallError::Extract(e.to_string()))?;
+            zip.extract(dest)
+                .map_err(|e| InstallError::Extract(e.to_string()))
+        }
+    }
+}
+
+/// A path next to `dest`, used while installing into it.
+fn sibling(dest: &Path, purpose: &str) -> PathBuf {
+    let name = dest
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "server".to_string());
+    dest.with_file_name(format!(".{}.{}-{}", name, purpose, std::process::id()))
+}
+
+/// Downloads and extracts an artifact into `dest`. The build is extracted into a staging
+/// directory beside `dest`, then the old build is renamed out of the way and the new one renamed
+/// into its place. `dest` never holds a partly extracted build, but it doesn't exist for the
+/// moment between the two renames, and the old build is moved back if the second rename fails.
+/// Builds marked as broken are refused.
+///
+/// If `expected_sha256` is given, the archive must have that SHA-256 checksum. The checksum of
+/// the archive is returned, and a [`BuildManifest`] recording every file is written into the
+/// build. `mirrors` and `progress` are passed to [`download`].
+pub fn install(
+    artifact: &Artifact,
+    mirrors: &[String],
+    dest: &Path,
+    expected_sha256: Option<&str>,
+    progress: &mut dyn FnMut(u64, Option<u64>),
+) -> Result<Installed, InstallError> {
+    if artifact.broken {
+        return Err(InstallError::Broken(artifact.num));
+    }
+    let format = ArchiveFormat::from_file_name(&artifact.archive)
+        .ok_or_else(|| InstallError::UnknownFormat(artifact.archive.clone()))?;
+    if let Some(parent) = dest.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+
+    let archive = sibling(dest, &format!("download.{}", artifact.archive));
+    let staging = sibling(dest, "staging");
+    let old = sibling(dest, "old");
+    let _ = fs::remove_dir_all(&staging);
+
+    let result = (|| {
+        let warnings = download(artifact, mirrors, &archive, progress)?;
+        let actual = integrity::sha256_file(&archive)?;
+        if let Some(expected) = expected_sha256 {
+            if !expected.eq_ignore_ascii_case(&actual) {
+                return Err(InstallError::ChecksumMismatch {
+                    expected: expected.to_lowercase(),
+                    actual,
+                });
+            }
+        }
+        extract(&archive, format, &staging)?;
+        BuildManifest::generate(&staging, artifact, &actual)?.write(&staging)?;
+        Ok(Installed {
+            sha256: actual,
+            warnings,
+        })
+    })();
+    let _ = fs::remove_file(&archive);
+    let mut installed = match result {
+        Ok(installed) => installed,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+    };
+
+    let had_old = dest.exists();
+    if had_old {
+        if let Err(e) = fs::rename(dest, &old) {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e.into());
+        }
+    }
+    if let Err(e) = fs::rename(&staging, dest) {
+        if had_old {
+            let _ = fs::rename(&old, dest);
+        }
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e.into());
+    }
+    // The new build is in place, so failing to clean up the old one isn't a failed install
+    if had_old {
+        if let Err(e) = fs::remove_dir_all(&old) {
+            installed.warnings.push(format!(
+                "the old build couldn't be removed from {}: {}",
+                old.display(),
+                e
+            ));
+        }
+    }
+    Ok(installed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{serve, temp_dir};
+
+    fn build_tar_xz(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, contents.as_bytes())
+                .unwrap();
+        }
+        let tar = builder.into_inner().unwrap();
+        let mut xz = Vec::new();
+        lzma_rs::xz_compress(&mut &tar[..], &mut xz).unwrap();
+        xz
+    }
+
+    #[test]
+    fn installs_and_replaces_a_build() {
+        let listing = "<a href=\"./5848-4f71128ee48b07026d6d7229a60ebc5f40f2b9db/fx.tar.xz\">\
+                       LATEST RECOMMENDED (5848)</a>\
+                       <a href=\"./5850-8a2c7e0d8b2b4c0e9f2a1b6d5c3e7f9a0b1c2d3e/fx.tar.xz\">\
+                       5850 (revoked)</a>\
+                       <a href=\"./5848-4f71128ee48b07026d6d7229a60ebc5f40f2b9db/fx.tar.xz\">5848</a>"
+            .as_bytes()
+            .to_vec();
+        let second = build_tar_xz(&[("alpine/run.sh", "second")]);
+        let url = serve(vec![
+            ("200 OK", listing),
+            ("200 OK", build_tar_xz(&[("run.sh", "first")])),
+            ("200 OK", second.clone()),
+            ("200 OK", second),
+            ("404 Not Found", Vec::new()),
+        ]);
+        let dir = temp_dir("install");
+        let dest = dir.join("server");
+
+        let mut server = ArtifactServer::new(&url);
+        let artifact = resolve_artifact(&mut server, "recommended").unwrap();
+        assert_eq!(artifact.num, 5848);
+        assert_eq!(artifact.archive, "fx.tar.xz");
+        assert!(!artifact.broken);
+
+        let latest = resolve_artifact(&mut server, "latest").unwrap();
+        assert_eq!(latest.num, 5850);
+        assert!(matches!(
+            install(&latest, &[], &dest, None, &mut |_, _| ()),
+            Err(InstallError::Broken(5850))
+        ));
+
+        let mut last = 0;
+        let first = install(&artifact, &[], &dest, None, &mut |received, _| {
+            last = received
+        })
+        .unwrap();
+        assert!(first.warnings.is_empty());
+        assert!(last > 0);
+        assert_eq!(fs::read_to_string(dest.join("run.sh")).unwrap(), "first");
+        assert!(integrity::verify_build(&dest).unwrap().is_ok());
+
+        // A download which doesn't match the checksum expected is thrown away
+        assert!(matches!(
+            install(&artifact, &[], &dest, Some(&first.sha256), &mut |_, _| ()),
+            Err(InstallError::ChecksumMismatch { .. })
+        ));
+        assert!(dest.join("run.sh").exists());
+
+        install(&artifact, &[], &dest, None, &mut |_, _| ()).unwrap();
+        assert!(!dest.join("run.sh").exists());
+        assert_eq!(
+            fs::read_to_string(dest.join("alpine/run.sh")).unwrap(),
+            "second"
+        );
+
+        // A failed download leaves the installed build alone
+        assert!(matches!(
+            install(&artifact, &[], &dest, None, &mut |_, _| ()),
+            Err(InstallError::Status(404))
+        ));
+        assert!(dest.join("alpine/run.sh").exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::artifacts::{self, Artifact, ListingCache};
+
+/// The file name of the manifest written into a build when it is installed.
+pub const BUILD_MANIFEST: &str = ".fivem-utility-manifest.json";
+
+/// Formats a SHA-256 digest as lowercase hex.
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Calculates the SHA-256 checksum of a file, as lowercase hex.
+pub fn sha256_file<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+/// Reads a SHA-256 checksum from the contents of a checksum file, in either the bare form or
+/// the `sha256sum` form (`<checksum>  <file name>`).
+pub fn parse_checksum(text: &str) -> Option<String> {
+    static CHECKSUM: OnceLock<Regex> = OnceLock::new();
+    let re = CHECKSUM.get_or_init(|| Regex::new(r"(?i)\b([\da-f]{64})\b").unwrap());
+    re.captures(text).map(|capture| capture[1].to_lowercase())
+}
+
+/// Fetches the checksum published beside an artifact's archive (`<archive>.sha256`), trying
+/// the artifact server and then each of `mirrors` as [`Artifact::download_urls`] does. If
+/// `cache` is given, checksums are read through it, so a checksum fetched before is used in
+/// offline mode and nothing is fetched otherwise. `None` is returned if no checksum could be
+/// read.
+pub fn published_checksum(
+    artifact: &Artifact,
+    mirrors: &[String],
+    cache: Option<&ListingCache>,
+) -> Option<String> {
+    artifact.download_urls(mirrors).iter().find_map(|url| {
+        let url = format!("{}.sha256", url);
+        let body = match cache {
+            Some(cache) if artifacts::local_path(&url).is_none() => cache.fetch(&url).ok()?.body,
+            _ => artifacts::fetch_body(&url).ok()?,
+        };
+        parse_checksum(&body)
+    })
+}
+
+/// A record of every file in an installed build, so changes to it can be detected later.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildManifest {
+    /// The artifact number of the build.
+    pub artifact: u16,
+    /// The hash of the build.
+    pub hash: String,
+    /// The SHA-256 checksum of the archive the build was extracted from.
+    pub archive_sha256: String,
+    /// Every file in the build, mapping its path (relative to the build, separated by `/`) to
+    /// its SHA-256 checksum. Symbolic links are recorded as `link:<target>`.
+    pub files: BTreeMap<String, String>,
+}
+
+/// The result of checking an installed build against its manifest.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct BuildVerification {
+    /// The artifact number of the build, from the manifest.
+    pub artifact: u16,
+    /// Files whose contents have changed.
+    pub modified: Vec<String>,
+    /// Files in the manifest which no longer exist.
+    pub missing: Vec<String>,
+    /// Files which aren't in the manifest.
+    pub extra: Vec<String>,
+}
+
+impl BuildVerification {
+    /// Whether the build matches its manifest exactly.
+    pub fn is_ok(&self) -> bool {
+        self.modified.is_empty() && self.missing.is_empty() && self.extra.is_empty()
+    }
+}
+
+/// The error returned when a build couldn't be checked against its manifest.
+#[derive(Debug)]
+pub enum ManifestError {
+    /// The build has no manifest, so wasn't installed by this tool.
+    NotFound,
+    /// The manifest isn't valid.
+    Parse(serde_json::Error),
+    /// A file couldn't be read.
+    Io(io::Error),
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestError::NotFound => write!(
+                f,
+                "no {} found, so the build wasn't installed by `artifacts install`",
+                BUILD_MANIFEST
+            ),
+            ManifestError::Parse(e) => write!(f, "invalid build manifest: {}", e),
+            ManifestError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+impl From<io::Error> for ManifestError {
+    fn from(e: io::Error) -> Self {
+        ManifestError::Io(e)
+    }
+}
+
+/// Calculates the manifest entry of every file under `dir`, leaving out the manifest itself.
+fn hash_tree(dir: &Path) -> io::Result<BTreeMap<String, String>> {
+    fn walk(root: &Path, dir: &Path, files: &mut BTreeMap<String, String>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let relative = path
+                .strip_prefix(root)
+                .unwrap()
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let file_type = entry.file_type()?;
+            if file_type.is_symlink() {
+                let target = fs::read_link(&path)?;
+                files.insert(relative, format!("link:{}", target.to_string_lossy()));
+            } else if file_type.is_dir() {
+                walk(root, &path, files)?;
+            } else if relative != BUILD_MANIFEST {
+                files.insert(relative, sha256_file(&path)?);
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = BTreeMap::new();
+    walk(dir, dir, &mut files)?;
+    Ok(files)
+}
+
+impl BuildManifest {
+    /// Records every file in the build extracted into `dir`.
+    pub fn generate(dir: &Path, artifact: &Artifact, archive_sha256: &str) -> io::Result<Self> {
+        Ok(Self {
+            artifact: artifact.num,
+            hash: artifact.hash.clone(),
+            archive_sha256: archive_sha256.to_string(),
+            files: hash_tree(dir)?,
+        })
+    }
+
+    /// Reads the manifest of the build in `dir`.
+    pub fn read(dir: &Path) -> Result<Self, ManifestError> {
+        let text = match fs::read_to_string(dir.join(BUILD_MANIFEST)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(ManifestError::NotFound),
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_str(&text).map_err(ManifestError::Parse)
+    }
+
+    /// Writes the manifest into the build in `dir`.
+    pub fn write(&self, dir: &Path) -> io::Result<()> {
+        let mut text = serde_json::to_string_pretty(self).unwrap();
+        text.push('\n');
+        fs::write(dir.join(BUILD_MANIFEST), text)
+    }
+
+    /// Checks the build in `dir` against the manifest.
+    pub fn verify(&self, dir: &Path) -> io::Result<BuildVerification> {
+        let actual = hash_tree(dir)?;
+        let mut report = BuildVerification {
+            artifact: self.artifact,
+            ..Default::default()
+        };
+        for (path, checksum) in &self.files {
+            match actual.get(path) {
+                None => report.missing.push(path.clone()),
+                Some(found) if found != checksum => report.modified.push(path.clone()),
+                Some(_) => (),
+            }
+        }
+        report.extra = actual
+            .into_keys()
+            .filter(|path| !self.files.contains_key(path))
+            .collect();
+        Ok(report)
+    }
+}
+
+/// Checks the build installed in `dir` against the manifest written when it was installed.
+pub fn verify_build(dir: &Path) -> Result<BuildVerification, ManifestError> {
+    let manifest = BuildManifest::read(dir)?;
+    Ok(manifest.verify(dir)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{serve, temp_dir};
+    use std::time::Duration;
+
+    #[test]
+    fn parses_checksum_files() {
+        let checksum = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+        assert_eq!(parse_checksum(checksum), Some(checksum.to_string()));
+        assert_eq!(
+            parse_checksum(&format!("{}  fx.tar.xz\n", checksum.to_uppercase())),
+            Some(checksum.to_string())
+        );
+        assert_eq!(parse_checksum("<html>Not Found</html>"), None);
+    }
+
+    #[test]
+    fn reads_published_checksums_from_mirrors_and_the_cache() {
+        let checksum = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+        let upstream = serve(vec![("404 Not Found", Vec::new())]);
+        let mirror = serve(vec![(
+            "200 OK",
+            format!("{}  fx.tar.xz\n", checksum).into_bytes(),
+        )]);
+        let artifact = Artifact {
+            url: format!("{}5848-abc/", upstream),
+            num: 5848,
+            hash: "abc".to_string(),
+            archive: "fx.tar.xz".to_string(),
+            broken: false,
+        };
+        let dir = temp_dir("published-checksum");
+        let mut cache = ListingCache {
+            dir: dir.clone(),
+            ttl: Duration::from_secs(300),
+            offline: false,
+        };
+        let mirrors = [mirror];
+        assert_eq!(
+            published_checksum(&artifact, &mirrors, Some(&cache)),
+            Some(checksum.to_string())
+        );
+
+        // Offline, the checksum is read from the cache without contacting either server
+        cache.offline = true;
+        assert_eq!(
+            published_checksum(&artifact, &mirrors, Some(&cache)),
+            Some(checksum.to_string())
+        );
+        cache.dir = dir.join("empty");
+        assert_eq!(published_checksum(&artifact, &mirrors, Some(&cache)), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn detects_changes_to_a_build() {
+        let dir = temp_dir("verify");
+        fs::create_dir_all(dir.join("alpine/opt")).unwrap();
+        fs::write(dir.join("run.sh"), "test").unwrap();
+        fs::write(dir.join("alpine/opt/server"), "binary").unwrap();
+        fs::write(dir.join("alpine/opt/citizen.dll"), "library").unwrap();
+
+        let artifact = Artifact {
+            url: String::new(),
+            num: 5848,
+            hash: "4f71128ee48b07026d6d7229a60ebc5f40f2b9db".to_string(),
+            archive: "fx.tar.xz".to_string(),
+            broken: false,
+        };
+        let manifest = BuildManifest::generate(&dir, &artifact, "").unwrap();
+        assert_eq!(
+            manifest.files["run.sh"],
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        );
+        manifest.write(&dir).unwrap();
+        assert!(verify_build(&dir).unwrap().is_ok());
+
+        fs::write(dir.join("alpine/opt/server"), "tampered").unwrap();
+        fs::remove_file(dir.join("alpine/opt/citizen.dll")).unwrap();
+        fs::write(dir.join("extra.txt"), "").unwrap();
+        let report = verify_build(&dir).unwrap();
+        assert_eq!(report.modified, vec!["alpine/opt/server"]);
+        assert_eq!(report.missing, vec!["alpine/opt/citizen.dll"]);
+        assert_eq!(report.extra, vec!["extra.txt"]);
+
+        fs::remove_file(dir.join(BUILD_MANIFEST)).unwrap();
+        assert!(matches!(verify_build(&dir), Err(ManifestError::NotFound)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
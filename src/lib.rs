@@ -0,0 +1,61 @@
+#![deny(missing_docs)]
+
+//! A crate of utility functions for FiveM, including function related to
+//! versioning and config management. The `config` module contains
+//! parsers for the `.cfg` files. The `artifacts` module contains functions
+//! to download a list of artifacts available from the artifact server. The
+//! `resources` module contains functions to find the resources in a
+//! resources folder and compare them with those a config starts.
+//!
+//! The `fivem-utility` command line tool is built on this library. Its
+//! dependencies are behind the `cli` feature, which is on by default, so
+//! projects using only the library can turn it off with
+//! `default-features = false`.
+
+/// The artifacts module contains functions for fetching information about available
+/// artifacts from the artifact server.
+pub mod artifacts;
+/// The config module contains functions for parsing and making sense of `.cfg`
+/// files that servers use to start.
+pub mod config;
+/// The dependencies module contains functions for checking resources are started after the
+/// resources they depend on.
+pub mod dependencies;
+/// The graph module contains functions for building and rendering a graph of resources and
+/// their dependencies.
+pub mod graph;
+/// The install module contains functions for downloading and extracting server builds from
+/// the artifact server.
+pub mod install;
+/// The integrity module contains functions for checking downloaded and installed builds haven't
+/// been corrupted or tampered with.
+pub mod integrity;
+/// The lint module contains a linter for configs, with rules which can be configured in
+/// `.fivemlint.toml`.
+pub mod lint;
+/// The lockfile module contains functions for reading and writing `fivem.lock`, which records
+/// the server build a server is pinned to.
+pub mod lockfile;
+/// The manifest module contains functions for parsing the `fxmanifest.lua` and
+/// `__resource.lua` files which describe resources.
+pub mod manifest;
+/// The mirror module contains functions for copying artifacts into a local directory laid out
+/// like the artifact server, so it can be used in place of it.
+pub mod mirror;
+/// The resources module contains functions for finding the resources in a resources folder and
+/// comparing them with the resources a config starts.
+pub mod resources;
+/// The secrets module contains functions for finding secrets in configs, and hiding them from
+/// output.
+pub mod secrets;
+/// The settings module contains the settings file of the tool, and how artifact server URLs are
+/// chosen.
+pub mod settings;
+/// The template module contains functions for rendering configs from templates, with
+/// variables, conditional blocks and overlays for each environment.
+pub mod template;
+/// The test_util module contains helpers shared by the tests of the other modules.
+#[cfg(test)]
+mod test_util;
+
+pub use resources::{detect_resources, scan_resources};
@@ -0,0 +1,535 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[cfg(feature = "cli")]
+use colored::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::document::Statement;
+use crate::config::edit::ConfigTree;
+use crate::config::{setting, Convar, ConvarScope, Diagnostic, Severity, SETTING_COMMANDS};
+
+/// The default file name of the linter's config file.
+pub const LINT_CONFIG: &str = ".fivemlint.toml";
+
+/// The most clients a server can have without a higher license tier, used unless the linter's
+/// config gives another limit.
+pub const DEFAULT_MAX_CLIENTS: u16 = 48;
+
+/// A check the linter makes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rule {
+    /// The name the rule is configured by.
+    pub name: &'static str,
+    /// What the rule checks for.
+    pub description: &'static str,
+    /// How serious problems found by the rule are, unless configured otherwise.
+    pub severity: Severity,
+}
+
+/// Every rule the linter has.
+pub const RULES: &[Rule] = &[
+    Rule {
+        name: "missing-license-key",
+        description: "`sv_licenseKey` is never set",
+        severity: Severity::Error,
+    },
+    Rule {
+        name: "weak-rcon-password",
+        description: "`rcon_password` is a default or easily guessed password",
+        severity: Severity::Error,
+    },
+    Rule {
+        name: "max-clients-over-limit",
+        description: "`sv_maxclients` is above the limit of the license tier",
+        severity: Severity::Error,
+    },
+    Rule {
+        name: "scripthook-in-production",
+        description: "`sv_scriptHookAllowed 1` is set in production",
+        severity: Severity::Error,
+    },
+    Rule {
+        name: "missing-endpoint",
+        description: "`endpoint_add_tcp` or `endpoint_add_udp` is never run",
+        severity: Severity::Error,
+    },
+    Rule {
+        name: "unknown-command",
+        description: "a command the server doesn't know is run",
+        severity: Severity::Warning,
+    },
+    Rule {
+        name: "duplicate-convar",
+        description: "a convar is set more than once",
+        severity: Severity::Warning,
+    },
+];
+
+/// Other commands which can be run from a config.
+const COMMANDS: &[&str] = &[
+    "exec",
+    "set",
+    "sets",
+    "setr",
+    "seta",
+    "start",
+    "ensure",
+    "stop",
+    "restart",
+    "refresh",
+    "endpoint_add_tcp",
+    "endpoint_add_udp",
+    "add_ace",
+    "remove_ace",
+    "add_principal",
+    "remove_principal",
+    "add_unsafe_worker_permission",
+    "add_filesystem_permission",
+    "load_server_icon",
+    "wait",
+    "say",
+];
+
+/// Passwords which are defaults or too easily guessed to be used for RCON.
+const WEAK_PASSWORDS: &[&str] = &[
+    "changeme",
+    "password",
+    "rcon",
+    "admin",
+    "default",
+    "fivem",
+    "server",
+    "qwerty",
+    "12345678",
+    "123456789",
+];
+
+/// How a rule is configured in `.fivemlint.toml`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleLevel {
+    /// The rule isn't checked.
+    Off,
+    /// Problems found by the rule are warnings.
+    #[serde(alias = "warn")]
+    Warning,
+    /// Problems found by the rule are errors.
+    Error,
+}
+
+/// The linter's config, read from `.fivemlint.toml`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LintConfig {
+    /// Whether the config is for a production server.
+    pub production: bool,
+    /// The most clients the license tier allows. Defaults to [`DEFAULT_MAX_CLIENTS`].
+    pub max_clients: Option<u16>,
+    /// Commands which aren't built into the server but are known, such as those added by
+    /// resources.
+    pub known_commands: Vec<String>,
+    /// The level of each rule which isn't left at its default, by the rule's name.
+    pub rules: BTreeMap<String, RuleLevel>,
+}
+
+/// The error returned when the linter's config couldn't be read.
+#[derive(Debug)]
+pub enum LintConfigError {
+    /// The config couldn't be read.
+    Io(io::Error),
+    /// The config isn't valid.
+    Parse(toml::de::Error),
+    /// The config configures a rule which doesn't exist.
+    UnknownRule(String),
+}
+
+impl fmt::Display for LintConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LintConfigError::Io(e) => write!(f, "{}", e),
+            LintConfigError::Parse(e) => write!(f, "invalid lint config: {}", e),
+            LintConfigError::UnknownRule(name) => write!(f, "there is no rule named `{}`", name),
+        }
+    }
+}
+
+impl std::error::Error for LintConfigError {}
+
+impl LintConfig {
+    /// Parses the text of a linter's config.
+    pub fn parse(text: &str) -> Result<Self, LintConfigError> {
+        let config: Self = toml::from_str(text).map_err(LintConfigError::Parse)?;
+        if let Some(name) = config
+            .rules
+            .keys()
+            .find(|name| !RULES.iter().any(|rule| rule.name == name.as_str()))
+        {
+            return Err(LintConfigError::UnknownRule(name.clone()));
+        }
+        Ok(config)
+    }
+
+    /// Reads the linter's config. If it doesn't exist, every rule is left at its default.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, LintConfigError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(LintConfigError::Io(e)),
+        }
+    }
+
+    /// How serious problems found by a rule are, or `None` if the rule is turned off.
+    pub fn severity(&self, rule: &Rule) -> Option<Severity> {
+        match self.rules.get(rule.name) {
+            None => Some(rule.severity),
+            Some(RuleLevel::Off) => None,
+            Some(RuleLevel::Warning) => Some(Severity::Warning),
+            Some(RuleLevel::Error) => Some(Severity::Error),
+        }
+    }
+}
+
+/// A problem found by the linter.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Lint {
+    /// The name of the rule which found the problem.
+    pub rule: &'static str,
+    /// The problem, and where it was found.
+    #[serde(flatten)]
+    pub diagnostic: Diagnostic,
+}
+
+/// Escapes text for use in a GitHub Actions workflow command.
+fn escape_annotation(text: &str, property: bool) -> String {
+    let text = text
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A");
+    if property {
+        text.replace(':', "%3A").replace(',', "%2C")
+    } else {
+        text
+    }
+}
+
+impl Lint {
+    /// Formats the problem as a GitHub Actions workflow command, so it is shown as an
+    /// annotation on the file in CI.
+    pub fn github_annotation(&self) -> String {
+        let d = &self.diagnostic;
+        let mut properties = format!("file={}", escape_annotation(&d.file, true));
+        if d.line > 0 {
+            properties.push_str(&format!(",line={},col={}", d.line, d.column));
+        }
+        properties.push_str(&format!(",title={}", self.rule));
+        format!(
+            "::{} {}::{}",
+            d.severity,
+            properties,
+            escape_annotation(&d.message, false)
+        )
+    }
+
+    /// Prints the problem to stderr in a format similar to a compiler, using colors as well.
+    #[cfg(feature = "cli")]
+    pub fn print_nicely(&self) {
+        self.diagnostic.print_nicely();
+        eprintln!("  {} rule: {}", "=".blue().bold(), self.rule);
+    }
+}
+
+/// Why a password is too weak to be used for RCON, if it is.
+fn password_weakness(password: &str) -> Option<&'static str> {
+    if password.chars().count() < 8 {
+        Some("shorter than 8 characters")
+    } else if WEAK_PASSWORDS
+        .iter()
+        .any(|weak| weak.eq_ignore_ascii_case(password))
+    {
+        Some("a default or common password")
+    } else if password.chars().all(|c| password.starts_with(c)) {
+        Some("a single repeated character")
+    } else {
+        None
+    }
+}
+
+/// Collects problems as the rules are checked, applying the linter's config to each.
+struct Linter<'a> {
+    tree: &'a ConfigTree,
+    config: &'a LintConfig,
+    lints: Vec<Lint>,
+}
+
+impl Linter<'_> {
+    fn report(
+        &mut self,
+        rule: &'static str,
+        at: Option<(usize, usize, &Statement, usize)>,
+        message: String,
+    ) {
+        let rule = RULES.iter().find(|r| r.name == rule).unwrap();
+        let severity = match self.config.severity(rule) {
+            Some(severity) => severity,
+            None => return,
+        };
+        let diagnostic = match at {
+            Some((d, l, statement, arg)) => Diagnostic {
+                severity,
+                message,
+                file: self.tree.documents[d].path.clone(),
+                line: l + 1,
+                column: statement.column(arg),
+                directive: Some(statement.command()),
+                snippet: Some(statement.to_string()),
+            },
+            None => Diagnostic {
+                severity,
+                message,
+                file: self.tree.documents[0].path.clone(),
+                line: 0,
+                column: 0,
+                directive: None,
+                snippet: None,
+            },
+        };
+        self.lints.push(Lint {
+            rule: rule.name,
+            diagnostic,
+        });
+    }
+}
+
+/// Checks every file in a config against the linter's rules, returning the problems found in
+/// the order the config is executed.
+pub fn lint(tree: &ConfigTree, config: &LintConfig) -> Vec<Lint> {
+    let mut linter = Linter {
+        tree,
+        config,
+        lints: Vec::new(),
+    };
+    if tree.documents.is_empty() {
+        return linter.lints;
+    }
+
+    let max_clients = config.max_clients.unwrap_or(DEFAULT_MAX_CLIENTS);
+    let mut license_key = false;
+    let mut tcp_endpoint = false;
+    let mut udp_endpoint = false;
+    let mut convars: HashMap<String, Convar> = HashMap::new();
+    for (d, l, statement) in tree.statements() {
+        let command = statement.command();
+        let known = SETTING_COMMANDS
+            .iter()
+            .chain(COMMANDS)
+            .any(|c| c.eq_ignore_ascii_case(&command))
+            || config
+                .known_commands
+                .iter()
+                .any(|c| c.eq_ignore_ascii_case(&command));
+        if !known {
+            linter.report(
+                "unknown-command",
+                Some((d, l, statement, 0)),
+                format!("`{}` isn't a known command", command),
+            );
+        }
+        tcp_endpoint |= command.eq_ignore_ascii_case("endpoint_add_tcp");
+        udp_endpoint |= command.eq_ignore_ascii_case("endpoint_add_udp");
+
+        let (name, arg) = match setting(statement) {
+            Some(setting) => setting,
+            None => continue,
+        };
+        let value = statement.arg(arg).unwrap_or_default();
+        let at = Some((d, l, statement, arg + 1));
+        // A setting's own command, such as `sv_maxclients 48`, sets it as `set` does
+        let convar = Convar {
+            name: name.clone(),
+            value: value.clone(),
+            scope: ConvarScope::from_command(&command).unwrap_or(ConvarScope::Server),
+            file: tree.documents[d].path.clone(),
+            line: l + 1,
+        };
+        if let Some(previous) = convars.get(&name.to_lowercase()) {
+            linter.report(
+                "duplicate-convar",
+                Some((d, l, statement, arg)),
+                convar.redefinition_warning(previous),
+            );
+        }
+        convars.insert(name.to_lowercase(), convar);
+        match name.to_lowercase().as_str() {
+            "sv_licensekey" => license_key |= !value.is_empty(),
+            "rcon_password" => {
+                if let Some(weakness) = password_weakness(&value) {
+                    linter.report(
+                        "weak-rcon-password",
+                        at,
+                        format!("`rcon_password` is {}", weakness),
+                    );
+                }
+            }
+            "sv_maxclients" if value.parse::<u16>().is_ok_and(|max| max > max_clients) => {
+                linter.report(
+                    "max-clients-over-limit",
+                    at,
+                    format!(
+                        "`sv_maxclients` is {}, but the license tier allows at most {}",
+                        value, max_clients
+                    ),
+                );
+            }
+            "sv_scripthookallowed" if config.production && value == "1" => {
+                linter.report(
+                    "scripthook-in-production",
+                    at,
+                    "ScriptHook shouldn't be allowed on a production server".to_string(),
+                );
+            }
+            _ => (),
+        }
+    }
+
+    if !license_key {
+        linter.report(
+            "missing-license-key",
+            None,
+            "`sv_licenseKey` is never set, so the server won't start".to_string(),
+        );
+    }
+    for (found, command) in [
+        (tcp_endpoint, "endpoint_add_tcp"),
+        (udp_endpoint, "endpoint_add_udp"),
+    ] {
+        if !found {
+            linter.report(
+                "missing-endpoint",
+                None,
+                format!("`{}` is never run, so players can't connect", command),
+            );
+        }
+    }
+    linter.lints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Document;
+
+    fn lint_text(text: &str, config: &LintConfig) -> Vec<Lint> {
+        let tree = ConfigTree::from_document(Document::parse("server.cfg", text));
+        lint(&tree, config)
+    }
+
+    #[test]
+    fn finds_problems_with_the_default_rules() {
+        let lints = lint_text(
+            "ENDPOINT_ADD_TCP \"0.0.0.0:30120\"\n\
+             set rcon_password changeme\n\
+             sv_maxclients 64\n\
+             sv_scriptHookAllowed 1\n\
+             set sv_maxclients 32\n\
+             start_resource chat\n",
+            &LintConfig::default(),
+        );
+        let found: Vec<(&str, usize)> = lints.iter().map(|l| (l.rule, l.diagnostic.line)).collect();
+        assert_eq!(
+            found,
+            vec![
+                ("weak-rcon-password", 2),
+                ("max-clients-over-limit", 3),
+                ("duplicate-convar", 5),
+                ("unknown-command", 6),
+                ("missing-license-key", 0),
+                ("missing-endpoint", 0),
+            ]
+        );
+        assert_eq!(lints[0].diagnostic.column, 19);
+        assert_eq!(
+            lints[2].diagnostic.message,
+            "`sv_maxclients` is set again, overriding the value set at server.cfg:3"
+        );
+        assert_eq!(
+            lints[5].github_annotation(),
+            "::error file=server.cfg,title=missing-endpoint::`endpoint_add_udp` is never run, so players can't connect"
+        );
+        assert_eq!(
+            lints[3].github_annotation(),
+            "::warning file=server.cfg,line=6,col=1,title=unknown-command::`start_resource` isn't a known command"
+        );
+    }
+
+    #[test]
+    fn rules_can_be_configured() {
+        let config = LintConfig::parse(
+            "production = true\n\
+             max_clients = 128\n\
+             known_commands = [\"start_resource\"]\n\
+             [rules]\n\
+             missing-endpoint = \"off\"\n\
+             missing-license-key = \"warn\"\n",
+        )
+        .unwrap();
+        let lints = lint_text(
+            "sv_maxclients 64\nsv_scriptHookAllowed 1\nstart_resource chat\n",
+            &config,
+        );
+        let found: Vec<(&str, Severity)> = lints
+            .iter()
+            .map(|l| (l.rule, l.diagnostic.severity))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("scripthook-in-production", Severity::Error),
+                ("missing-license-key", Severity::Warning),
+            ]
+        );
+
+        assert!(matches!(
+            LintConfig::parse("[rules]\nno-such-rule = \"off\"\n"),
+            Err(LintConfigError::UnknownRule(_))
+        ));
+        assert!(matches!(
+            LintConfig::parse("[rules]\nunknown-command = \"loud\"\n"),
+            Err(LintConfigError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn unrendered_secrets_are_linted_from_their_template() {
+        let dir = crate::test_util::temp_dir("lint-secrets");
+        let main = dir.join("server.cfg").to_string_lossy().into_owned();
+        fs::write(
+            &main,
+            "endpoint_add_tcp \"0.0.0.0:30120\"\nexec secrets.cfg\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("secrets.cfg.template"),
+            "sv_licenseKey \"${SV_LICENSEKEY}\"\n",
+        )
+        .unwrap();
+
+        let rules = |tree: &ConfigTree| -> Vec<&str> {
+            lint(tree, &LintConfig::default())
+                .iter()
+                .map(|l| l.rule)
+                .collect()
+        };
+        let tree = ConfigTree::load(&main).unwrap();
+        assert_eq!(
+            rules(&tree),
+            vec!["missing-license-key", "missing-endpoint"]
+        );
+        let tree = ConfigTree::checked(Document::read(&main).unwrap());
+        assert_eq!(rules(&tree), vec!["missing-endpoint"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
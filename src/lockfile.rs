@@ -0,0 +1,138 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::artifacts::{Artifact, Platform};
+
+/// The default file name of the lockfile.
+pub const LOCKFILE: &str = "fivem.lock";
+
+/// The server build a server is pinned to, as recorded in `fivem.lock`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    /// The platform the build is for.
+    pub platform: Platform,
+    /// The artifact number of the build.
+    pub artifact: u16,
+    /// The hash of the build.
+    pub hash: String,
+    /// The directory the build is installed in.
+    pub path: String,
+    /// The SHA-256 checksum of the build's archive, recorded when it was installed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+/// The error returned when the lockfile couldn't be read or written.
+#[derive(Debug)]
+pub enum LockfileError {
+    /// The lockfile couldn't be read or written.
+    Io(io::Error),
+    /// The lockfile isn't valid.
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for LockfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockfileError::Io(e) => write!(f, "{}", e),
+            LockfileError::Parse(e) => write!(f, "invalid lockfile: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LockfileError {}
+
+impl Lockfile {
+    /// Creates a lockfile pinning an artifact installed in `path`, whose archive had the
+    /// checksum `sha256`.
+    pub fn new(platform: Platform, artifact: &Artifact, path: &str, sha256: &str) -> Self {
+        Self {
+            platform,
+            artifact: artifact.num,
+            hash: artifact.hash.clone(),
+            path: path.to_string(),
+            sha256: Some(sha256.to_string()),
+        }
+    }
+
+    /// The checksum recorded for an artifact, if it is the one pinned.
+    pub fn sha256_for(&self, artifact: &Artifact) -> Option<&str> {
+        if artifact.num == self.artifact && artifact.hash == self.hash {
+            self.sha256.as_deref()
+        } else {
+            None
+        }
+    }
+
+    /// Reads a lockfile.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, LockfileError> {
+        let text = fs::read_to_string(path).map_err(LockfileError::Io)?;
+        serde_json::from_str(&text).map_err(LockfileError::Parse)
+    }
+
+    /// Writes the lockfile, replacing any lockfile already there.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), LockfileError> {
+        let mut text = serde_json::to_string_pretty(self).map_err(LockfileError::Parse)?;
+        text.push('\n');
+        fs::write(path, text).map_err(LockfileError::Io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn reads_what_it_writes() {
+        let dir = temp_dir("lockfile");
+        let path = dir.join(LOCKFILE);
+
+        let artifact = Artifact {
+            url: "https://example.com/5181-abc/".to_string(),
+            num: 5181,
+            hash: "abc".to_string(),
+            archive: "fx.tar.xz".to_string(),
+            broken: false,
+        };
+        let lock = Lockfile::new(Platform::Linux, &artifact, "server", "0123abcd");
+        lock.write(&path).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("\"platform\": \"linux\""));
+        assert!(text.ends_with("}\n"));
+        assert_eq!(Lockfile::read(&path).unwrap(), lock);
+        assert_eq!(lock.sha256_for(&artifact), Some("0123abcd"));
+        let other = Artifact {
+            num: 5182,
+            ..artifact
+        };
+        assert_eq!(lock.sha256_for(&other), None);
+
+        // Lockfiles written before checksums were recorded can still be read
+        fs::write(
+            &path,
+            r#"{"platform": "windows", "artifact": 5000, "hash": "def", "path": "srv"}"#,
+        )
+        .unwrap();
+        let old = Lockfile::read(&path).unwrap();
+        assert_eq!(old.platform, Platform::Windows);
+        assert_eq!(old.sha256, None);
+        old.write(&path).unwrap();
+        assert!(!fs::read_to_string(&path).unwrap().contains("sha256"));
+
+        fs::write(&path, "{\"platform\": \"linux\", \"artifact\": \"soon\"}").unwrap();
+        let error = Lockfile::read(&path).unwrap_err();
+        assert!(matches!(error, LockfileError::Parse(_)));
+        assert!(error.to_string().starts_with("invalid lockfile: "));
+
+        fs::remove_dir_all(&dir).unwrap();
+        match Lockfile::read(&path) {
+            Err(LockfileError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::NotFound),
+            other => panic!("expected a missing lockfile, got {:?}", other),
+        }
+    }
+}
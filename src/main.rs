@@ -1,58 +1,302 @@
 #![deny(missing_docs)]
 
-//! A crate of utility functions for FiveM, including function related to
-//! versioning and config management. The `config` module contains
-//! parsers for the `.cfg` files. The `artifacts` module contains functions
-//! to download a list of artifacts available from the artifact server.
+//! The `fivem-utility` command line tool, which checks and edits server configs and manages
+//! server builds using the `fivem_utility` library.
 
-use clap::{Parser, Subcommand};
+use clap::{ArgEnum, Parser, Subcommand};
 use colored::*;
+use fivem_utility::resources::{detect_resources, resource_usage};
+use fivem_utility::{
+    artifacts, config, dependencies, graph, install, integrity, lint, lockfile, manifest, mirror,
+    secrets, settings, template,
+};
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::Serialize;
 
 use std::collections::HashMap;
-use std::fs;
+use std::path::Path;
 use std::process::exit;
+use std::time::Duration;
 
-/// The artifacts module contains functions for fetching information about available
-/// artifacts from the artifact server.
-pub mod artifacts;
-/// The config module contains functions for parsing and making sense of `.cfg`
-/// files that servers use to start.
-pub mod config;
-
-/// A function to detect resources within a resources folder.
-pub fn detect_resources(resource_dir: &str) -> HashMap<String, String> {
-    let paths = fs::read_dir(resource_dir).unwrap();
-    let mut resources: HashMap<String, String> = HashMap::new();
-
-    for path in paths {
-        let entry = path.unwrap();
-        let meta = entry.metadata().unwrap();
-        if meta.is_dir() {
-            let name = entry.file_name().into_string().unwrap();
-            let file_path = entry.path().to_str().unwrap().to_owned();
-            if name.starts_with("[") && name.ends_with("]") {
-                // recurse downwards
-                let sub_resources = detect_resources(&file_path);
-                for key in sub_resources.keys() {
-                    resources.insert(key.clone(), sub_resources[key].clone());
-                }
-            } else {
-                resources.insert(name, file_path);
-            }
+/// The output module contains the formats commands can print their results in.
+mod output;
+
+use output::{OutputFormat, Printer};
+
+/// The result of verifying a config.
+#[derive(Clone, Debug, Serialize)]
+struct VerifyReport {
+    ok: bool,
+    errors: usize,
+    warnings: usize,
+    diagnostics: Vec<config::Diagnostic>,
+}
+
+/// The result of linting a config.
+#[derive(Clone, Debug, Serialize)]
+struct LintReport {
+    ok: bool,
+    errors: usize,
+    warnings: usize,
+    lints: Vec<lint::Lint>,
+}
+
+/// A change made to a file when editing the config.
+#[derive(Clone, Debug, Serialize)]
+struct FileChange {
+    file: String,
+    diff: String,
+}
+
+/// How the build a server is pinned to compares with the recommended build.
+#[derive(Clone, Debug, Serialize)]
+struct ArtifactStatus {
+    platform: artifacts::Platform,
+    pinned: u16,
+    hash: String,
+    path: String,
+    installed: bool,
+    recommended: u16,
+    behind: usize,
+}
+
+/// The result of editing the config.
+#[derive(Clone, Debug, Serialize)]
+struct EditReport {
+    changes: Vec<FileChange>,
+    written: bool,
+}
+
+/// Checks the resources started by the config against the dependencies in their manifests.
+fn check_dependencies(
+    cfg: &config::FivemConfig,
+    resources_dir: &str,
+) -> dependencies::DependencyReport {
+    let manifests = manifest::read_manifests(&detect_resources_or_exit(resources_dir));
+    dependencies::check_start_order(&cfg.resources, &manifests)
+}
+
+/// Finds the resources in the resources folder, or prints why it couldn't be read and exits.
+fn detect_resources_or_exit(resources_dir: &str) -> HashMap<String, String> {
+    detect_resources(resources_dir).unwrap_or_else(|e| {
+        eprintln!("Failed to read resources folder: {}", e);
+        exit(1);
+    })
+}
+
+/// Makes an edit to the config, printing a diff of the changes before writing them. Returns
+/// whether anything was changed.
+fn edit_config(
+    config_file: &str,
+    dry_run: bool,
+    output: &Printer,
+    edit: impl FnOnce(&mut config::edit::ConfigTree) -> Result<(), config::edit::EditError>,
+) -> bool {
+    let mut tree = config::edit::ConfigTree::load(config_file).unwrap_or_else(|e| {
+        eprintln!("Failed to read config file `{}`: {}", config_file, e);
+        exit(1);
+    });
+    let mut found = secrets::scan(&tree);
+    if let Err(e) = edit(&mut tree) {
+        eprintln!("{} {}", "Failed to edit config:".red(), e);
+        exit(1);
+    }
+
+    // The diff shows the lines secrets are set on, so secrets are hidden from it even without
+    // `--redact`, both those the edit replaces and those it adds
+    found.extend(secrets::scan(&tree));
+    let redactor = secrets::Redactor::new(&found);
+    let changes: Vec<FileChange> = tree
+        .changes()
+        .into_iter()
+        .map(|(doc, original)| FileChange {
+            file: doc.path.clone(),
+            diff: redactor.redact(&config::edit::unified_diff(
+                &doc.path,
+                original,
+                &doc.to_string(),
+            )),
+        })
+        .collect();
+    let changed = !changes.is_empty();
+    let write = |tree: &mut config::edit::ConfigTree| {
+        if changed && !dry_run {
+            tree.write_changes()
         } else {
-            // Check if symlink
-            let poss_symlink = fs::metadata(entry.path()).unwrap();
-            if poss_symlink.is_dir() {
-                // Is symlink to dir
-                let sub_resources = detect_resources(&entry.path().to_str().unwrap().to_owned());
-                for key in sub_resources.keys() {
-                    resources.insert(key.clone(), sub_resources[key].clone());
-                }
+            Ok(())
+        }
+    };
+    if output.is_structured() {
+        let result = write(&mut tree);
+        output.print(&EditReport {
+            written: changed && !dry_run && result.is_ok(),
+            changes,
+        });
+        if let Err(e) = result {
+            eprintln!("Failed to write config: {}", e);
+            exit(1);
+        }
+        return changed;
+    }
+    if !changed {
+        eprintln!("Nothing to change.");
+        return changed;
+    }
+    for change in changes {
+        for line in change.diff.lines() {
+            if line.starts_with("+++") || line.starts_with("---") {
+                println!("{}", line.bold());
+            } else if line.starts_with('+') {
+                println!("{}", line.green());
+            } else if line.starts_with('-') {
+                println!("{}", line.red());
+            } else if line.starts_with("@@") {
+                println!("{}", line.cyan());
+            } else {
+                println!("{}", line);
             }
         }
     }
+    write(&mut tree).unwrap_or_else(|e| {
+        eprintln!("Failed to write config: {}", e);
+        exit(1);
+    });
+    changed
+}
+
+/// Creates a progress bar for a download.
+fn download_bar() -> ProgressBar {
+    let bar = ProgressBar::new(0);
+    bar.set_style(
+        ProgressStyle::with_template(
+            "{spinner} [{bar:40}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+        )
+        .unwrap()
+        .progress_chars("=> "),
+    );
+    bar
+}
 
-    resources
+/// Prints warnings returned by the library, such as mirrors which couldn't be used.
+fn print_warnings(warnings: &[String]) {
+    for warning in warnings {
+        eprintln!("{} {}", "warning:".yellow().bold(), warning);
+    }
+}
+
+/// Installs a build, showing the progress of the download, or prints why it couldn't be
+/// installed and exits. The download is tried from each of `mirrors` if the artifact server
+/// fails. The download is checked against `sha256` if given, then the checksum recorded in
+/// `lock`, then the checksum published by the artifact server or a mirror, read through `cache`.
+/// The checksum of the download is returned.
+fn install_or_exit(
+    artifact: &artifacts::Artifact,
+    mirrors: &[String],
+    cache: &artifacts::ListingCache,
+    dest: &str,
+    sha256: Option<String>,
+    lock: Option<&lockfile::Lockfile>,
+) -> String {
+    eprintln!(
+        "Installing artifact {} ({}) into {}",
+        artifact.num.to_string().bold(),
+        artifact.hash,
+        dest
+    );
+    let expected = sha256
+        .or_else(|| lock.and_then(|lock| lock.sha256_for(artifact).map(String::from)))
+        .or_else(|| integrity::published_checksum(artifact, mirrors, Some(cache)));
+    if expected.is_none() {
+        eprintln!(
+            "{} no checksum is known for this build, so its checksum will be recorded instead",
+            "warning:".yellow().bold()
+        );
+    }
+
+    let bar = download_bar();
+    let result = install::install(
+        artifact,
+        mirrors,
+        Path::new(dest),
+        expected.as_deref(),
+        &mut |received, total| {
+            if let Some(total) = total {
+                bar.set_length(total);
+            }
+            bar.set_position(received);
+        },
+    );
+    bar.finish_and_clear();
+    let installed = result.unwrap_or_else(|e| {
+        eprintln!("{} {}", "Failed to install artifact:".red(), e);
+        exit(1);
+    });
+    print_warnings(&installed.warnings);
+    installed.sha256
+}
+
+/// Creates an artifact server which tries each URL in turn, caching listings on disk.
+fn artifact_server<'a>(
+    urls: &'a [String],
+    cache: &artifacts::ListingCache,
+) -> artifacts::ArtifactServer<'a> {
+    urls[1..].iter().fold(
+        artifacts::ArtifactServer::new(&urls[0]).with_cache(cache.clone()),
+        |server, url| server.with_mirror(url),
+    )
+}
+
+/// Finds the artifact for a version, or prints why it couldn't be found and exits.
+fn resolve_or_exit(server: &mut artifacts::ArtifactServer, version: &str) -> artifacts::Artifact {
+    let artifact = install::resolve_artifact(server, version);
+    print_warnings(&server.take_warnings());
+    artifact.unwrap_or_else(|e| {
+        eprintln!("{} {}", "Failed to find artifact:".red(), e);
+        exit(1);
+    })
+}
+
+/// Reads the lockfile, or prints why it couldn't be read and exits.
+fn read_lockfile_or_exit(path: &str) -> lockfile::Lockfile {
+    lockfile::Lockfile::read(path).unwrap_or_else(|e| {
+        eprintln!(
+            "Failed to read lockfile `{}` ({}). Maybe run `artifacts install` first?",
+            path, e
+        );
+        exit(1);
+    })
+}
+
+/// Writes the lockfile, or prints why it couldn't be written and exits.
+fn write_lockfile_or_exit(lock: &lockfile::Lockfile, path: &str) {
+    lock.write(path).unwrap_or_else(|e| {
+        eprintln!("Failed to write lockfile `{}`: {}", path, e);
+        exit(1);
+    });
+}
+
+/// Reads the config file, or prints why it couldn't be parsed and exits.
+fn read_config_or_exit(file_name: &str) -> config::FivemConfig {
+    config::read_config_file(file_name).unwrap_or_else(|e| {
+        eprintln!(
+            "Failed to parse config file ({}). Maybe run `verify` to check why?",
+            e
+        );
+        exit(1);
+    })
+}
+
+/// Renders the config from its template for an environment, or prints why it couldn't be
+/// rendered and exits.
+fn render_or_exit(config: &str, env: Option<&str>) -> config::document::Document {
+    template::render_config(config, env, &|name| std::env::var(name).ok()).unwrap_or_else(|e| {
+        for diagnostic in &e.diagnostics {
+            diagnostic.print_nicely();
+            eprintln!();
+        }
+        eprintln!("{} {}", "Failed to render config:".red(), e);
+        exit(1);
+    })
 }
 
 #[derive(Parser, Debug)]
@@ -66,6 +310,33 @@ struct Args {
     #[clap(short, long, default_value = "resources")]
     resources_dir: String,
 
+    /// Set the format results are printed in
+    #[clap(long, arg_enum, default_value = "table")]
+    format: OutputFormat,
+
+    /// Hide secrets found in the configs a command reads from everything printed, in every format
+    #[clap(long)]
+    redact: bool,
+
+    /// Set how many seconds artifact server listings are cached for before checking for changes
+    #[clap(long, default_value = "300")]
+    cache_ttl: u64,
+
+    /// Only use cached artifact server listings, never contacting the artifact server
+    #[clap(long)]
+    offline: bool,
+
+    /// Use this artifact server instead of the upstream one. Give it more than once to fall
+    /// back across mirrors. `{platform}` stands for `linux` or `windows`. Defaults to
+    /// FIVEM_ARTIFACT_URL_LINUX or FIVEM_ARTIFACT_URL_WINDOWS, then FIVEM_ARTIFACT_URL
+    /// (separated by commas), then the settings file
+    #[clap(long = "artifact-url", multiple_occurrences = true)]
+    artifact_urls: Vec<String>,
+
+    /// Set the settings file
+    #[clap(long, default_value = settings::SETTINGS_FILE)]
+    settings: String,
+
     #[clap(subcommand)]
     subcmd: SubCommand,
 }
@@ -73,108 +344,1031 @@ struct Args {
 #[derive(Subcommand, Debug)]
 enum SubCommand {
     /// Print details about the config file.
-    Print,
+    Print(PrintArgs),
     /// Checks the integrity of the config file.
-    Verify,
+    Verify(VerifyArgs),
+    /// Compares what two config files do, such as the convars they set and the resources they
+    /// start, ignoring formatting and comments. Secrets are masked.
+    Diff {
+        /// The old config file
+        old: String,
+        /// The new config file
+        new: String,
+    },
+    /// Formats the config file and every file it `exec`s, grouping statements into sections and
+    /// normalising whitespace and quoting.
+    Fmt(FmtArgs),
+    /// Checks the config against the linter's rules, which are configured in `.fivemlint.toml`.
+    Lint(LintArgs),
     /// Finds resources specified in server.cfg, and lists resources that are never used.
     ResourceUsage,
     /// Gives information about the versions available from the FiveM version server.
     VersionServer(VersionServerArgs),
+    /// Edits the config file in place, keeping its formatting and comments.
+    Config(ConfigArgs),
+    /// Prints a graph of resources and their dependencies.
+    Graph(GraphArgs),
+    /// Downloads and installs server builds from the artifact server.
+    Artifacts(ArtifactsArgs),
+    /// Copies builds into a directory laid out like the artifact server, so it can be used as a
+    /// mirror with `--artifact-url file://<dir>/`.
+    Mirror(MirrorArgs),
+    /// Finds secrets in the config, such as passwords, keys and tokens.
+    Secrets(SecretsArgs),
+    /// Writes the config from its template, `server.base.cfg` if there is one, filling in
+    /// variables from the environment and applying the overlay for an environment.
+    Render(RenderArgs),
+}
+
+#[derive(Parser, Debug)]
+struct PrintArgs {
+    /// Print the RCON password and license key in JSON and YAML output instead of masking them
+    #[clap(long)]
+    show_secrets: bool,
+}
+
+#[derive(Parser, Debug)]
+struct FmtArgs {
+    /// Print the changes formatting would make without writing them, exiting with an error if
+    /// there are any
+    #[clap(long)]
+    check: bool,
+
+    /// Sort convars by name
+    #[clap(long)]
+    sort_convars: bool,
+}
+
+#[derive(Parser, Debug)]
+struct VerifyArgs {
+    /// Check the config as rendered from its template for this environment
+    #[clap(long)]
+    env: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+struct RenderArgs {
+    /// Render the config for this environment, applying `server.<env>.cfg` over the template
+    #[clap(long)]
+    env: Option<String>,
+
+    /// Set the file the config is written to, which is the config file by default
+    #[clap(short, long)]
+    output: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+struct SecretsArgs {
+    /// Set the secrets file, which is `secrets.cfg` beside the config by default
+    #[clap(long)]
+    file: Option<String>,
+
+    #[clap(subcommand)]
+    subcmd: SecretsSubCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum SecretsSubCommand {
+    /// Lists every secret in the config and every file it `exec`s, exiting with an error if any
+    /// are found.
+    Scan,
+    /// Moves the license key, RCON password, Steam Web API key and connection strings into the
+    /// secrets file, which is ignored by git, and writes a template it can be rendered from.
+    Extract {
+        /// Print the changes that would be made without writing them
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Writes the secrets file from its template, filling in secrets from environment
+    /// variables.
+    Render {
+        /// Set the template, which is the secrets file with `.template` added by default
+        #[clap(long)]
+        template: Option<String>,
+    },
+}
+
+#[derive(Parser, Debug)]
+struct MirrorArgs {
+    /// The artifact numbers to copy, or any of `latest`, `recommended` or `optional`
+    #[clap(default_value = "recommended")]
+    versions: Vec<String>,
+
+    /// The directory of the mirror
+    #[clap(long)]
+    dest: String,
+
+    /// Copy builds from the Windows artifact server instead of the Linux one
+    #[clap(short = 'w', long)]
+    use_windows_server: bool,
+}
+
+#[derive(Parser, Debug)]
+struct LintArgs {
+    /// Set the linter's config file
+    #[clap(long, default_value = lint::LINT_CONFIG)]
+    lint_config: String,
+
+    /// Check the config as a production server's, as if `production = true` were set in the
+    /// linter's config
+    #[clap(long)]
+    production: bool,
+
+    /// Print problems as GitHub Actions annotations, so they are shown on the config in CI
+    #[clap(long)]
+    github: bool,
+
+    /// Lint the config as rendered from its template for this environment
+    #[clap(long)]
+    env: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+struct ArtifactsArgs {
+    /// Use the Windows artifact server instead of the Linux one when installing
+    #[clap(short = 'w', long)]
+    use_windows_server: bool,
+
+    /// Set the lockfile recording the build the server is pinned to
+    #[clap(long, default_value = lockfile::LOCKFILE)]
+    lockfile: String,
+
+    #[clap(subcommand)]
+    subcmd: ArtifactsSubCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum ArtifactsSubCommand {
+    /// Downloads a build and extracts it, replacing any build already there.
+    Install {
+        /// The artifact number, or one of `latest`, `recommended` or `optional`
+        version: String,
+
+        /// The directory to install the build into
+        #[clap(long)]
+        dest: String,
+
+        /// The SHA-256 checksum the downloaded archive must have
+        #[clap(long)]
+        sha256: Option<String>,
+    },
+    /// Moves the pinned build to another version and installs it.
+    Upgrade {
+        /// Upgrade to this artifact number
+        #[clap(long, conflicts_with = "recommended")]
+        to: Option<u16>,
+
+        /// Upgrade to the recommended build (the default)
+        #[clap(long)]
+        recommended: bool,
+
+        /// The SHA-256 checksum the downloaded archive must have
+        #[clap(long)]
+        sha256: Option<String>,
+    },
+    /// Shows the pinned build and how far behind the recommended build it is.
+    Status,
+    /// Checks an installed build's files haven't changed since it was installed.
+    Verify {
+        /// The directory the build is installed in
+        dir: String,
+    },
+}
+
+#[derive(Parser, Debug)]
+struct GraphArgs {
+    /// The style to draw the graph in. Use the global `--format` for JSON or YAML instead
+    #[clap(short, long, arg_enum, default_value = "dot")]
+    style: GraphStyle,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum GraphStyle {
+    Dot,
+    Mermaid,
+}
+
+#[derive(Parser, Debug)]
+struct ConfigArgs {
+    /// Print the changes that would be made without writing them
+    #[clap(long)]
+    dry_run: bool,
+
+    #[clap(subcommand)]
+    subcmd: ConfigSubCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigSubCommand {
+    /// Sets a convar, changing the existing definition if there is one.
+    Set(ConfigSetArgs),
+    /// Removes every definition of a convar.
+    Unset {
+        /// The name of the convar
+        name: String,
+    },
+    /// Adds or removes resources started by the config.
+    Ensure {
+        #[clap(subcommand)]
+        subcmd: EnsureSubCommand,
+    },
+}
+
+#[derive(Parser, Debug)]
+struct ConfigSetArgs {
+    /// The name of the convar
+    name: String,
+
+    /// The value to set the convar to
+    value: String,
+
+    /// Replicate the convar to clients (`setr`)
+    #[clap(long, conflicts_with = "serverinfo")]
+    replicated: bool,
+
+    /// Show the convar in the server info (`sets`)
+    #[clap(long)]
+    serverinfo: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum EnsureSubCommand {
+    /// Adds an `ensure` for a resource.
+    Add {
+        /// The name of the resource
+        resource: String,
+
+        /// Add the resource directly after this resource, rather than after the last resource
+        #[clap(long)]
+        after: Option<String>,
+    },
+    /// Removes every `ensure` or `start` of a resource.
+    Remove {
+        /// The name of the resource
+        resource: String,
+    },
 }
 
 #[derive(Parser, Debug)]
 struct VersionServerArgs {
-    /// Get the URL of a server download from the version server
+    /// Get the URL of a server download from the version server, by artifact number or one of
+    /// `recommended`, `optional` or `latest`. `latest` is now the newest build rather than
+    /// the recommended build; use `recommended` for that
     #[clap(short, long)]
     get_url: Option<String>,
 
     /// Use the Windows artifact server instead of the Linux one
     #[clap(short = 'w', long)]
     use_windows_server: bool,
+
+    #[clap(subcommand)]
+    subcmd: Option<VersionServerSubCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+enum VersionServerSubCommand {
+    /// Lists the builds between two builds, with a summary of each one's changes.
+    Diff {
+        /// The build to compare from: an artifact number, `pinned` (the build in the lockfile),
+        /// or one of `latest`, `recommended` or `optional`
+        from: String,
+
+        /// The build to compare to, in the same form
+        #[clap(default_value = "recommended")]
+        to: String,
+
+        /// Where each build's summary is read from, with `{hash}` standing for its commit hash.
+        /// Defaults to the settings file, or the GitHub commits API, which is sent the token in
+        /// GITHUB_TOKEN if it is set so it allows more requests
+        #[clap(long)]
+        changelog_url: Option<String>,
+
+        /// Set the lockfile `pinned` is read from
+        #[clap(long, default_value = lockfile::LOCKFILE)]
+        lockfile: String,
+    },
 }
 
 fn main() {
     let args = Args::parse();
+    let mut output = Printer {
+        format: args.format,
+        redactor: args.redact.then(|| {
+            config::edit::ConfigTree::load(&args.config)
+                .map(|tree| secrets::Redactor::new(&secrets::scan(&tree)))
+                .unwrap_or_default()
+        }),
+    };
+    let cache = artifacts::ListingCache::new(Duration::from_secs(args.cache_ttl), args.offline);
+    let settings = settings::Settings::read(&args.settings).unwrap_or_else(|e| {
+        eprintln!("Failed to read settings file `{}`: {}", args.settings, e);
+        exit(1);
+    });
+    let artifact_urls =
+        |platform| settings::artifact_urls(platform, &args.artifact_urls, &settings);
 
     match args.subcmd {
-        SubCommand::Print => {
-            let cfg = config::read_config_file(&args.config)
-                .ok()
-                .unwrap_or_else(|| {
-                    panic!("Failed to parse config file. Maybe run `verify` to check why?");
+        SubCommand::Print(print_args) => {
+            let mut cfg = read_config_or_exit(&args.config);
+            if output.is_structured() {
+                let mut value = serde_json::to_value(&cfg).unwrap();
+                if print_args.show_secrets {
+                    value["rcon_password"] = cfg.rcon_password.into();
+                    value["licensekey"] = cfg.licensekey.into();
+                }
+                output.print(&value);
+            } else {
+                if let Some(redactor) = &output.redactor {
+                    redactor.redact_config(&mut cfg);
+                }
+                cfg.print_nicely();
+            }
+        }
+        SubCommand::Verify(verify_args) => {
+            let cfg = match &verify_args.env {
+                Some(env) => template::render_config(&args.config, Some(env), &|name| {
+                    std::env::var(name).ok()
+                })
+                .and_then(|doc| {
+                    output.redact_configs(&[config::edit::ConfigTree::from_document(doc.clone())]);
+                    config::read_config_document(&doc)
+                }),
+                None => config::read_config_file(&args.config),
+            };
+            let mut diagnostics = match cfg {
+                Ok(cfg) => {
+                    let mut diagnostics = cfg.diagnostics.clone();
+                    if Path::new(&args.resources_dir).is_dir() {
+                        let report = check_dependencies(&cfg, &args.resources_dir);
+                        diagnostics.extend(report.diagnostics());
+                    }
+                    diagnostics
+                }
+                Err(e) => e.diagnostics,
+            };
+            diagnostics.sort_by_key(|d| std::cmp::Reverse(d.severity));
+
+            let errors = diagnostics
+                .iter()
+                .filter(|d| d.severity == config::Severity::Error)
+                .count();
+            let warnings = diagnostics.len() - errors;
+            if output.is_structured() {
+                output.print(&VerifyReport {
+                    ok: errors == 0,
+                    errors,
+                    warnings,
+                    diagnostics,
                 });
-            cfg.print_nicely();
+            } else {
+                for diagnostic in &mut diagnostics {
+                    if let Some(redactor) = &output.redactor {
+                        redactor.redact_diagnostic(diagnostic);
+                    }
+                    diagnostic.print_nicely();
+                    eprintln!();
+                }
+                if errors > 0 {
+                    eprintln!("The file was parsed and {} error(s) were found.", errors);
+                } else if warnings > 0 {
+                    eprintln!(
+                        "The file was parsed and found no errors, but {} warning(s).",
+                        warnings
+                    );
+                } else {
+                    eprintln!("The file was parsed and found no errors.");
+                }
+            }
+            if errors > 0 {
+                exit(1);
+            }
         }
-        SubCommand::Verify => {
-            let config_result = config::read_config_file(&args.config);
-            if config_result.is_ok() {
-                eprintln!("The file was parsed and found no errors.");
-                exit(0);
+        SubCommand::Lint(lint_args) => {
+            let mut lint_config =
+                lint::LintConfig::read(&lint_args.lint_config).unwrap_or_else(|e| {
+                    eprintln!(
+                        "Failed to read lint config `{}`: {}",
+                        lint_args.lint_config, e
+                    );
+                    exit(1);
+                });
+            lint_config.production |= lint_args.production;
+            let doc = match &lint_args.env {
+                Some(env) => render_or_exit(&args.config, Some(env)),
+                None => config::Document::read(&args.config).unwrap_or_else(|e| {
+                    eprintln!("Failed to read config file `{}`: {}", args.config, e);
+                    exit(1);
+                }),
+            };
+            let tree = config::edit::ConfigTree::checked(doc);
+            output.redact_configs(std::slice::from_ref(&tree));
+            let mut lints = lint::lint(&tree, &lint_config);
+
+            let errors = lints
+                .iter()
+                .filter(|l| l.diagnostic.severity == config::Severity::Error)
+                .count();
+            let warnings = lints.len() - errors;
+            if output.is_structured() {
+                output.print(&LintReport {
+                    ok: errors == 0,
+                    errors,
+                    warnings,
+                    lints,
+                });
+            } else if lint_args.github {
+                for lint in &lints {
+                    println!("{}", output.redact(&lint.github_annotation()));
+                }
             } else {
+                for lint in &mut lints {
+                    if let Some(redactor) = &output.redactor {
+                        redactor.redact_diagnostic(&mut lint.diagnostic);
+                    }
+                    lint.print_nicely();
+                    eprintln!();
+                }
                 eprintln!(
-                    "The file was parsed and error(s) were found: {}",
-                    config_result.err().unwrap()
+                    "The config was linted and {} error(s) and {} warning(s) were found.",
+                    errors, warnings
                 );
+            }
+            if errors > 0 {
+                exit(1);
+            }
+        }
+        SubCommand::Diff { old, new } => {
+            let trees: Vec<config::edit::ConfigTree> = [&old, &new]
+                .into_iter()
+                .filter_map(|path| config::edit::ConfigTree::load(path).ok())
+                .collect();
+            output.redact_configs(&trees);
+            let diff = config::diff::ConfigDiff::between(
+                &read_config_or_exit(&old),
+                &read_config_or_exit(&new),
+            );
+            if output.is_structured() {
+                output.print(&diff);
+            } else if diff.is_empty() {
+                eprintln!("{} and {} do the same thing.", old, new);
+            } else {
+                diff.print_nicely();
+            }
+        }
+        SubCommand::Fmt(fmt_args) => {
+            let options = config::format::FormatOptions {
+                sort_convars: fmt_args.sort_convars,
+            };
+            let changed = edit_config(&args.config, fmt_args.check, &output, |tree| {
+                for doc in &mut tree.documents {
+                    *doc = config::format::format_document(doc, &options);
+                }
+                Ok(())
+            });
+            if fmt_args.check && changed {
                 exit(1);
             }
         }
         SubCommand::ResourceUsage => {
-            let cfg = config::read_config_file(&args.config)
-                .ok()
-                .unwrap_or_else(|| {
-                    panic!("Failed to parse config file. Maybe run `verify` to check why?");
-                });
-            let mut resources = detect_resources(&args.resources_dir);
-            for res in cfg.resources {
-                let found = resources.remove(&res);
-                match found {
-                    Some(val) => println!("{} {} @ {}", "[  FOUND  ]".green(), res.bold(), val),
-                    None => eprintln!("{} {}", "[ MISSING ]".red(), res.bold()),
-                };
+            let cfg = read_config_or_exit(&args.config);
+            let report = resource_usage(&cfg, &args.resources_dir).unwrap_or_else(|e| {
+                eprintln!("Failed to read resources folder: {}", e);
+                exit(1);
+            });
+            if output.is_structured() {
+                output.print(&report);
+                return;
+            }
+
+            for res in &report.found {
+                println!(
+                    "{} {} @ {}",
+                    "[  FOUND  ]".green(),
+                    res.name.bold(),
+                    res.path
+                );
+            }
+            for res in &report.not_resources {
+                eprintln!(
+                    "{} {} @ {} (no fxmanifest.lua or __resource.lua)",
+                    "[ NOT RES ]".red(),
+                    res.name.bold(),
+                    res.path
+                );
+            }
+            for res in &report.missing {
+                eprintln!("{} {}", "[ MISSING ]".red(), res.bold());
             }
-            for key in resources.keys() {
+            for res in &report.extra {
                 eprintln!(
                     "{} {} @ {}",
                     "[  EXTRA  ]".yellow(),
-                    key.bold(),
-                    &resources[key]
+                    res.name.bold(),
+                    res.path
+                );
+            }
+            for res in &report.ignored {
+                eprintln!(
+                    "{} {} @ {}",
+                    "[ NOT RES ]".yellow(),
+                    res.name.bold(),
+                    res.path
+                );
+            }
+
+            let report = report.dependencies;
+            if !report.is_ok() {
+                eprintln!();
+                for diagnostic in report.diagnostics() {
+                    diagnostic.print_nicely();
+                    eprintln!();
+                }
+                if report.cycles.is_empty() {
+                    eprintln!("{}", "Suggested start order:".bold());
+                } else {
+                    eprintln!(
+                        "{}",
+                        "Suggested start order (resources in cycles are left as they are):".bold()
+                    );
+                }
+                for res in &report.suggested_order {
+                    eprintln!("  ensure {}", res);
+                }
+            }
+        }
+        SubCommand::Config(cfg_args) => match cfg_args.subcmd {
+            ConfigSubCommand::Set(set_args) => {
+                let scope = if set_args.replicated {
+                    Some(config::ConvarScope::Replicated)
+                } else if set_args.serverinfo {
+                    Some(config::ConvarScope::ServerInfo)
+                } else {
+                    None
+                };
+                edit_config(&args.config, cfg_args.dry_run, &output, |tree| {
+                    tree.set_convar(&set_args.name, &set_args.value, scope)
+                });
+            }
+            ConfigSubCommand::Unset { name } => {
+                edit_config(&args.config, cfg_args.dry_run, &output, |tree| {
+                    tree.unset_convar(&name)
+                });
+            }
+            ConfigSubCommand::Ensure { subcmd } => match subcmd {
+                EnsureSubCommand::Add { resource, after } => {
+                    edit_config(&args.config, cfg_args.dry_run, &output, |tree| {
+                        tree.add_ensure(&resource, after.as_deref())
+                    });
+                }
+                EnsureSubCommand::Remove { resource } => {
+                    edit_config(&args.config, cfg_args.dry_run, &output, |tree| {
+                        tree.remove_ensure(&resource)
+                    });
+                }
+            },
+        },
+        SubCommand::Graph(graph_args) => {
+            let cfg = read_config_or_exit(&args.config);
+            let found = detect_resources_or_exit(&args.resources_dir);
+            let manifests = manifest::read_manifests(&found);
+            let graph = graph::ResourceGraph::build(&cfg.resources, &found, &manifests);
+            if output.is_structured() {
+                output.print(&graph);
+                return;
+            }
+            match graph_args.style {
+                GraphStyle::Dot => print!("{}", graph.to_dot()),
+                GraphStyle::Mermaid => print!("{}", graph.to_mermaid()),
+            }
+        }
+        SubCommand::Artifacts(art_args) => match art_args.subcmd {
+            ArtifactsSubCommand::Install {
+                version,
+                dest,
+                sha256,
+            } => {
+                let platform = if art_args.use_windows_server {
+                    artifacts::Platform::Windows
+                } else {
+                    artifacts::Platform::Linux
+                };
+                let urls = artifact_urls(platform);
+                let mut server = artifact_server(&urls, &cache);
+                let artifact = resolve_or_exit(&mut server, &version);
+                let existing = lockfile::Lockfile::read(&art_args.lockfile).ok();
+                let sha256 =
+                    install_or_exit(&artifact, &urls, &cache, &dest, sha256, existing.as_ref());
+                let lock = lockfile::Lockfile::new(platform, &artifact, &dest, &sha256);
+                write_lockfile_or_exit(&lock, &art_args.lockfile);
+
+                if output.is_structured() {
+                    output.print(&lock);
+                } else {
+                    eprintln!(
+                        "Installed artifact {} into {} and pinned it in {}",
+                        artifact.num, dest, art_args.lockfile
+                    );
+                }
+            }
+            ArtifactsSubCommand::Upgrade { to, sha256, .. } => {
+                let lock = read_lockfile_or_exit(&art_args.lockfile);
+                let urls = artifact_urls(lock.platform);
+                let mut server = artifact_server(&urls, &cache);
+                let artifact = match to {
+                    Some(num) => resolve_or_exit(&mut server, &num.to_string()),
+                    None => resolve_or_exit(&mut server, "recommended"),
+                };
+
+                if artifact.num == lock.artifact && Path::new(&lock.path).is_dir() {
+                    if output.is_structured() {
+                        output.print(&lock);
+                    } else {
+                        eprintln!("Already pinned to artifact {}.", artifact.num);
+                    }
+                    return;
+                }
+                let sha256 =
+                    install_or_exit(&artifact, &urls, &cache, &lock.path, sha256, Some(&lock));
+                let new_lock =
+                    lockfile::Lockfile::new(lock.platform, &artifact, &lock.path, &sha256);
+                write_lockfile_or_exit(&new_lock, &art_args.lockfile);
+
+                if output.is_structured() {
+                    output.print(&new_lock);
+                } else {
+                    eprintln!(
+                        "Moved from artifact {} to {}.",
+                        lock.artifact,
+                        artifact.num.to_string().bold()
+                    );
+                }
+            }
+            ArtifactsSubCommand::Status => {
+                let lock = read_lockfile_or_exit(&art_args.lockfile);
+                let urls = artifact_urls(lock.platform);
+                let mut server = artifact_server(&urls, &cache);
+                let recommended = server.get_latest_version_num();
+                let behind = server
+                    .get_artifacts()
+                    .iter()
+                    .filter(|a| a.num > lock.artifact && a.num <= recommended)
+                    .count();
+                print_warnings(&server.take_warnings());
+                let status = ArtifactStatus {
+                    installed: Path::new(&lock.path).is_dir(),
+                    platform: lock.platform,
+                    pinned: lock.artifact,
+                    hash: lock.hash,
+                    path: lock.path,
+                    recommended,
+                    behind,
+                };
+                if output.is_structured() {
+                    output.print(&status);
+                    return;
+                }
+
+                println!(
+                    "Pinned to artifact {} ({}) for {}, installed in {}",
+                    status.pinned.to_string().bold(),
+                    status.hash,
+                    status.platform,
+                    status.path
                 );
+                if !status.installed {
+                    eprintln!(
+                        "{} {} doesn't exist; run `artifacts upgrade --to {}` to install it",
+                        "[ MISSING ]".red(),
+                        status.path,
+                        status.pinned
+                    );
+                }
+                if let Err(e) = server.listing() {
+                    eprintln!("Couldn't read the artifact server: {}", e);
+                } else if recommended == 0 {
+                    eprintln!("Couldn't find the recommended build on the artifact server.");
+                } else if status.pinned >= recommended {
+                    println!(
+                        "{} The recommended build is {}",
+                        "[UP TO DATE]".green(),
+                        recommended
+                    );
+                } else {
+                    println!(
+                        "{} The recommended build is {}, {} build(s) ahead",
+                        "[ BEHIND ]".yellow(),
+                        recommended,
+                        behind
+                    );
+                }
+            }
+            ArtifactsSubCommand::Verify { dir } => {
+                let report = integrity::verify_build(Path::new(&dir)).unwrap_or_else(|e| {
+                    eprintln!("{} {}", "Failed to verify build:".red(), e);
+                    exit(1);
+                });
+                if output.is_structured() {
+                    output.print(&report);
+                } else {
+                    for file in &report.modified {
+                        eprintln!("{} {}", "[ CHANGED ]".red(), file);
+                    }
+                    for file in &report.missing {
+                        eprintln!("{} {}", "[ MISSING ]".red(), file);
+                    }
+                    for file in &report.extra {
+                        eprintln!("{} {}", "[  EXTRA  ]".yellow(), file);
+                    }
+                    if report.is_ok() {
+                        eprintln!(
+                            "Every file of artifact {} matches the manifest.",
+                            report.artifact
+                        );
+                    } else {
+                        eprintln!(
+                            "Artifact {} doesn't match the manifest: {} changed, {} missing, {} extra.",
+                            report.artifact,
+                            report.modified.len(),
+                            report.missing.len(),
+                            report.extra.len()
+                        );
+                    }
+                }
+                if !report.is_ok() {
+                    exit(1);
+                }
+            }
+        },
+        SubCommand::Secrets(secrets_args) => {
+            let secrets_file = secrets_args.file.unwrap_or_else(|| {
+                Path::new(&args.config)
+                    .with_file_name(secrets::SECRETS_FILE)
+                    .to_string_lossy()
+                    .into_owned()
+            });
+            let template_file = format!("{}{}", secrets_file, secrets::TEMPLATE_SUFFIX);
+            match secrets_args.subcmd {
+                SecretsSubCommand::Scan => {
+                    let tree = config::edit::ConfigTree::load(&args.config).unwrap_or_else(|e| {
+                        eprintln!("Failed to read config file `{}`: {}", args.config, e);
+                        exit(1);
+                    });
+                    let found = secrets::scan(&tree);
+                    if output.is_structured() {
+                        output.print(&found);
+                    } else {
+                        for secret in &found {
+                            eprintln!(
+                                "{} {}:{}:{} {} ({})",
+                                "[ SECRET ]".red(),
+                                secret.file,
+                                secret.line,
+                                secret.column,
+                                secret.masked.bold(),
+                                secret.description
+                            );
+                        }
+                        if found.is_empty() {
+                            eprintln!("No secrets were found in the config.");
+                        } else {
+                            eprintln!(
+                                "{} secret(s) were found. Move them out of the config, or keep \
+                                 the files they are in out of version control.",
+                                found.len()
+                            );
+                        }
+                    }
+                    if !found.is_empty() {
+                        exit(1);
+                    }
+                }
+                SecretsSubCommand::Extract { dry_run } => {
+                    let mut template = None;
+                    edit_config(&args.config, dry_run, &output, |tree| {
+                        let extracted = secrets::extract(tree, &secrets_file)?;
+                        if !extracted.secrets.is_empty() {
+                            template = Some(secrets::template(tree.document_mut(&extracted.path)?));
+                        }
+                        Ok(())
+                    });
+                    let template = match template {
+                        Some(template) if !dry_run => template,
+                        _ => return,
+                    };
+                    if let Err(e) = std::fs::write(&template_file, template) {
+                        eprintln!("Failed to write template `{}`: {}", template_file, e);
+                        exit(1);
+                    }
+                    match secrets::ignore_in_git(Path::new(&secrets_file)) {
+                        Ok(true) => eprintln!("{} is now ignored by git.", secrets_file),
+                        Ok(false) => (),
+                        Err(e) => {
+                            eprintln!(
+                                "{} {} isn't ignored by git: {}",
+                                "warning:".yellow().bold(),
+                                secrets_file,
+                                e
+                            )
+                        }
+                    }
+                    eprintln!(
+                        "Wrote {}. Commit it, and run `secrets render` with the environment \
+                         variables in it set to recreate {}.",
+                        template_file, secrets_file
+                    );
+                }
+                SecretsSubCommand::Render { template } => {
+                    let template_file = template.unwrap_or(template_file);
+                    let template = std::fs::read_to_string(&template_file).unwrap_or_else(|e| {
+                        eprintln!("Failed to read template `{}`: {}", template_file, e);
+                        exit(1);
+                    });
+                    let rendered = secrets::render(&template, |name| std::env::var(name).ok())
+                        .unwrap_or_else(|e| {
+                            eprintln!("{} {}", "Failed to render secrets:".red(), e);
+                            exit(1);
+                        });
+                    if let Err(e) = std::fs::write(&secrets_file, rendered) {
+                        eprintln!("Failed to write secrets file `{}`: {}", secrets_file, e);
+                        exit(1);
+                    }
+                    eprintln!("Rendered {} from {}.", secrets_file, template_file);
+                }
+            }
+        }
+        SubCommand::Render(render_args) => {
+            let base = template::base_path(&args.config);
+            if render_args.output.is_none() && !Path::new(&base).is_file() {
+                eprintln!(
+                    "There is no template `{}`, so rendering would overwrite `{}`. Move the \
+                     template there, or give `--output`.",
+                    base, args.config
+                );
+                exit(1);
+            }
+            let mut doc = render_or_exit(&args.config, render_args.env.as_deref());
+            doc.path = render_args.output.unwrap_or(args.config);
+            if let Err(e) = doc.write() {
+                eprintln!("Failed to write config file `{}`: {}", doc.path, e);
+                exit(1);
+            }
+            eprintln!("Rendered {}.", doc.path);
+        }
+        SubCommand::Mirror(mirror_args) => {
+            let urls = artifact_urls(if mirror_args.use_windows_server {
+                artifacts::Platform::Windows
+            } else {
+                artifacts::Platform::Linux
+            });
+            let mut server = artifact_server(&urls, &cache);
+            let dest = Path::new(&mirror_args.dest);
+            let mut mirrored = Vec::new();
+            for version in &mirror_args.versions {
+                let artifact = resolve_or_exit(&mut server, version);
+                let bar = download_bar();
+                let result = mirror::mirror_artifact(
+                    &artifact,
+                    &urls,
+                    Some(&cache),
+                    dest,
+                    &mut |received, total| {
+                        if let Some(total) = total {
+                            bar.set_length(total);
+                        }
+                        bar.set_position(received);
+                    },
+                );
+                bar.finish_and_clear();
+                let copy = result.unwrap_or_else(|e| {
+                    eprintln!("{} {}", "Failed to mirror artifact:".red(), e);
+                    exit(1);
+                });
+                print_warnings(&copy.warnings);
+                if !output.is_structured() {
+                    let status = if copy.existing {
+                        "[ PRESENT  ]".green()
+                    } else {
+                        "[ MIRRORED ]".green()
+                    };
+                    eprintln!("{} {} @ {}", status, copy.num.to_string().bold(), copy.path);
+                }
+                mirrored.push(copy);
+            }
+
+            let recommended = server.channel_num(artifacts::ArtifactChannel::Recommended);
+            let optional = server.channel_num(artifacts::ArtifactChannel::Optional);
+            if let Err(e) = mirror::write_index(dest, recommended, optional) {
+                eprintln!("{} {}", "Failed to write the mirror's index:".red(), e);
+                exit(1);
+            }
+            if output.is_structured() {
+                output.print(&mirrored);
             }
         }
         SubCommand::VersionServer(vs_args) => {
-            let url;
-            if vs_args.use_windows_server {
-                url = "https://runtime.fivem.net/artifacts/fivem/build_server_windows/master/";
+            let urls = artifact_urls(if vs_args.use_windows_server {
+                artifacts::Platform::Windows
             } else {
-                url = "https://runtime.fivem.net/artifacts/fivem/build_proot_linux/master/";
+                artifacts::Platform::Linux
+            });
+            let mut art_serv = artifact_server(&urls, &cache);
+            if let Err(e) = art_serv.listing() {
+                print_warnings(&art_serv.take_warnings());
+                eprintln!("{} {}", "Failed to read the artifact server:".red(), e);
+                exit(1);
             }
+            print_warnings(&art_serv.take_warnings());
 
-            let mut art_serv = artifacts::ArtifactServer::new(url);
+            if let Some(VersionServerSubCommand::Diff {
+                from,
+                to,
+                changelog_url,
+                lockfile,
+            }) = vs_args.subcmd
+            {
+                let mut resolve = |version: &str| {
+                    if version == "pinned" {
+                        read_lockfile_or_exit(&lockfile).artifact
+                    } else {
+                        resolve_or_exit(&mut art_serv, version).num
+                    }
+                };
+                let (from, to) = (resolve(&from), resolve(&to));
+                let mut diff =
+                    artifacts::ArtifactDiff::between(&art_serv.get_artifacts(), from, to);
+                let source = artifacts::ChangelogSource::new(
+                    changelog_url
+                        .as_deref()
+                        .or(settings.changelog_url.as_deref())
+                        .unwrap_or(artifacts::changelog::DEFAULT_CHANGELOG_URL),
+                )
+                .with_cache(cache.clone());
+                if let Err(e) = diff.fetch_summaries(&source) {
+                    eprintln!(
+                        "{} couldn't read the summary of every build: {}",
+                        "warning:".yellow().bold(),
+                        e
+                    );
+                }
+                if output.is_structured() {
+                    output.print(&diff);
+                    return;
+                }
 
-            if vs_args.get_url.is_some() {
-                let for_version = vs_args.get_url.unwrap();
-                let ar;
-                if for_version.eq_ignore_ascii_case("latest") {
-                    let latest_version = art_serv.get_latest_version_num();
-                    ar = art_serv.get_artifact(latest_version);
+                if from > to {
+                    eprintln!(
+                        "{} {} is older than {}, so these changes would be undone",
+                        "warning:".yellow().bold(),
+                        to,
+                        from
+                    );
+                }
+                for build in &diff.builds {
+                    println!(
+                        "{}\t{}\t{}{}",
+                        build.num.to_string().bold(),
+                        &build.hash[..build.hash.len().min(8)],
+                        build.summary.as_deref().unwrap_or("(no summary)"),
+                        if build.broken { " (broken)" } else { "" }
+                    );
+                }
+                println!(
+                    "Moving from {} to {} skips {} build(s)",
+                    from, to, diff.skipped
+                );
+            } else if let Some(for_version) = vs_args.get_url {
+                let ar = if let Ok(channel) = for_version.parse::<artifacts::ArtifactChannel>() {
+                    art_serv.channel(channel)
                 } else {
                     let for_version: u16 = for_version.parse().unwrap_or_else(|_| {
                         eprintln!("The version you specified is not valid!");
                         exit(1);
                     });
-                    ar = art_serv.get_artifact(for_version);
-                }
+                    art_serv.get_artifact(for_version)
+                };
 
-                if let Some(ar) = ar {
-                    println!("{}\t{}", ar.num, ar.url);
-                } else {
-                    eprintln!("The artifact you requested doesn't exist!");
+                match ar {
+                    Some(ar) if output.is_structured() => output.print(&ar),
+                    Some(ar) if ar.broken => println!("{}\t{}\t(broken)", ar.num, ar.url),
+                    Some(ar) => println!("{}\t{}", ar.num, ar.url),
+                    None => {
+                        eprintln!("The artifact you requested doesn't exist!");
+                        exit(1);
+                    }
                 }
             } else {
                 let mut afs = art_serv.get_artifacts();
                 afs.sort();
-                for af in afs {
-                    println!("{}\t{}", af.num, af.url);
+                if output.is_structured() {
+                    output.print(&afs);
+                } else {
+                    for af in afs {
+                        if af.broken {
+                            println!("{}\t{}\t(broken)", af.num, af.url);
+                        } else {
+                            println!("{}\t{}", af.num, af.url);
+                        }
+                    }
                 }
             }
         }
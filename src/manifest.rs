@@ -0,0 +1,410 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The file name of a modern resource manifest.
+pub const FXMANIFEST: &str = "fxmanifest.lua";
+/// The file name of a legacy resource manifest.
+pub const LEGACY_MANIFEST: &str = "__resource.lua";
+
+/// Which kind of manifest file a resource uses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ManifestKind {
+    /// A modern `fxmanifest.lua`.
+    FxManifest,
+    /// A legacy `__resource.lua`.
+    Legacy,
+}
+
+/// The contents of a resource manifest (`fxmanifest.lua` or `__resource.lua`).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResourceManifest {
+    /// Which kind of manifest file this was read from, if it was read from a file.
+    pub kind: Option<ManifestKind>,
+    /// The `fx_version` the resource targets.
+    pub fx_version: Option<String>,
+    /// The games (`game` or `games`) the resource supports.
+    pub games: Vec<String>,
+    /// The resources (and server requirements, starting with `/`) this resource depends on.
+    pub dependencies: Vec<String>,
+    /// The scripts run on the client.
+    pub client_scripts: Vec<String>,
+    /// The scripts run on the server.
+    pub server_scripts: Vec<String>,
+    /// The scripts run on both the client and the server.
+    pub shared_scripts: Vec<String>,
+    /// The files sent to clients.
+    pub files: Vec<String>,
+    /// The page used for the NUI frame of the resource.
+    pub ui_page: Option<String>,
+    /// The resource names this resource provides, so it can satisfy dependencies on them.
+    pub provides: Vec<String>,
+    /// Whether the resource uses Lua 5.4.
+    pub lua54: bool,
+    /// Any other entries in the manifest, such as `author`, `description` or `version`.
+    pub metadata: HashMap<String, Vec<String>>,
+}
+
+/// The error returned when a manifest couldn't be read.
+#[derive(Debug)]
+pub enum ManifestError {
+    /// The directory has no `fxmanifest.lua` or `__resource.lua`, so isn't a resource.
+    NotFound,
+    /// The manifest exists but couldn't be read.
+    Io(io::Error),
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestError::NotFound => write!(
+                f,
+                "no {} or {} found, so this is not a resource",
+                FXMANIFEST, LEGACY_MANIFEST
+            ),
+            ManifestError::Io(e) => write!(f, "failed to read manifest: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+/// A single token of the Lua manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum LuaToken {
+    Ident(String),
+    Str(String),
+    /// Any other symbol, such as brackets, commas or operators.
+    Symbol(char),
+    /// A number, or anything else not understood.
+    Other,
+}
+
+/// An internal function which breaks Lua source up into tokens, skipping comments.
+fn tokenize(source: &str) -> Vec<LuaToken> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '-' && chars.get(i + 1) == Some(&'-') {
+            i += 2;
+            if let Some((_, end)) = long_bracket(&chars, i) {
+                i = end;
+            } else {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+        } else if c == '[' && long_bracket(&chars, i).is_some() {
+            let (text, end) = long_bracket(&chars, i).unwrap();
+            tokens.push(LuaToken::Str(text));
+            i = end;
+        } else if c == '"' || c == '\'' {
+            let mut text = String::new();
+            i += 1;
+            while i < chars.len() && chars[i] != c && chars[i] != '\n' {
+                if chars[i] == '\\' && i + 1 < chars.len() {
+                    i += 1;
+                    text.push(match chars[i] {
+                        'n' => '\n',
+                        't' => '\t',
+                        other => other,
+                    });
+                } else {
+                    text.push(chars[i]);
+                }
+                i += 1;
+            }
+            i += 1;
+            tokens.push(LuaToken::Str(text));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(LuaToken::Ident(chars[start..i].iter().collect()));
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(LuaToken::Other);
+        } else {
+            tokens.push(LuaToken::Symbol(c));
+            i += 1;
+        }
+    }
+
+    tokens
+}
+
+/// If a Lua long bracket (`[[`, `[==[`, etc.) starts at `start`, returns its contents and the
+/// index just after it closes.
+fn long_bracket(chars: &[char], start: usize) -> Option<(String, usize)> {
+    if chars.get(start) != Some(&'[') {
+        return None;
+    }
+    let mut level = 0;
+    while chars.get(start + 1 + level) == Some(&'=') {
+        level += 1;
+    }
+    if chars.get(start + 1 + level) != Some(&'[') {
+        return None;
+    }
+
+    let mut close = vec![']'];
+    close.extend(std::iter::repeat_n('=', level));
+    close.push(']');
+
+    let body_start = start + 2 + level;
+    let mut i = body_start;
+    while i < chars.len() {
+        if chars[i..].starts_with(&close) {
+            return Some((chars[body_start..i].iter().collect(), i + close.len()));
+        }
+        i += 1;
+    }
+    Some((chars[body_start..].iter().collect(), chars.len()))
+}
+
+/// Collects every string in a table starting at `tokens[start]` (which must be `{`), returning
+/// them and the index just after the table closes. Keys of `key = value` entries are skipped.
+fn table_strings(tokens: &[LuaToken], start: usize) -> (Vec<String>, usize) {
+    let mut strings = Vec::new();
+    let mut depth = 0;
+    let mut i = start;
+    while i < tokens.len() {
+        match &tokens[i] {
+            LuaToken::Symbol('{') => depth += 1,
+            LuaToken::Symbol('}') => {
+                depth -= 1;
+                if depth == 0 {
+                    return (strings, i + 1);
+                }
+            }
+            LuaToken::Str(s) => strings.push(s.clone()),
+            _ => (),
+        }
+        i += 1;
+    }
+    (strings, i)
+}
+
+impl ResourceManifest {
+    /// Parses the source of a manifest file.
+    pub fn parse(source: &str) -> Self {
+        let tokens = tokenize(source);
+        let mut manifest = Self::default();
+
+        let mut i = 0;
+        while i < tokens.len() {
+            let name = match &tokens[i] {
+                LuaToken::Ident(name) => name.clone(),
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            };
+            i += 1;
+
+            // A directive is a call, which may be curried (`data_file 'TYPE' 'path'`)
+            let mut values = Vec::new();
+            let mut is_call = false;
+            loop {
+                match tokens.get(i) {
+                    Some(LuaToken::Str(s)) => {
+                        values.push(s.clone());
+                        i += 1;
+                    }
+                    Some(LuaToken::Symbol('{')) => {
+                        let (strings, end) = table_strings(&tokens, i);
+                        values.extend(strings);
+                        i = end;
+                    }
+                    Some(LuaToken::Symbol('(')) => {
+                        i += 1;
+                        while i < tokens.len() && tokens[i] != LuaToken::Symbol(')') {
+                            match &tokens[i] {
+                                LuaToken::Str(s) => {
+                                    values.push(s.clone());
+                                    i += 1;
+                                }
+                                LuaToken::Symbol('{') => {
+                                    let (strings, end) = table_strings(&tokens, i);
+                                    values.extend(strings);
+                                    i = end;
+                                }
+                                _ => i += 1,
+                            }
+                        }
+                        i += 1;
+                    }
+                    _ => break,
+                }
+                is_call = true;
+            }
+
+            if is_call {
+                manifest.add_directive(&name, values);
+            }
+        }
+
+        manifest
+    }
+
+    fn add_directive(&mut self, name: &str, values: Vec<String>) {
+        match name {
+            "fx_version" => self.fx_version = values.into_iter().next(),
+            "game" | "games" => self.games.extend(values),
+            "dependency" | "dependencies" => self.dependencies.extend(values),
+            "client_script" | "client_scripts" => self.client_scripts.extend(values),
+            "server_script" | "server_scripts" => self.server_scripts.extend(values),
+            "shared_script" | "shared_scripts" => self.shared_scripts.extend(values),
+            "file" | "files" => self.files.extend(values),
+            "ui_page" => self.ui_page = values.into_iter().next(),
+            "provide" | "provides" => self.provides.extend(values),
+            "lua54" => self.lua54 = values.first().map(|v| v == "yes").unwrap_or(false),
+            _ => self
+                .metadata
+                .entry(name.to_string())
+                .or_default()
+                .extend(values),
+        }
+    }
+
+    /// The names of the resources this resource depends on, leaving out server requirements
+    /// such as `/server:5181` or `/onesync`.
+    pub fn resource_dependencies(&self) -> Vec<&str> {
+        self.dependencies
+            .iter()
+            .filter(|d| !d.starts_with('/'))
+            .map(|d| d.as_str())
+            .collect()
+    }
+}
+
+/// Finds the manifest in a resource directory, preferring `fxmanifest.lua` to `__resource.lua`
+/// as the server does.
+pub fn find_manifest(resource_dir: &Path) -> Option<(PathBuf, ManifestKind)> {
+    let fxmanifest = resource_dir.join(FXMANIFEST);
+    if fxmanifest.is_file() {
+        return Some((fxmanifest, ManifestKind::FxManifest));
+    }
+    let legacy = resource_dir.join(LEGACY_MANIFEST);
+    if legacy.is_file() {
+        return Some((legacy, ManifestKind::Legacy));
+    }
+    None
+}
+
+/// Reads and parses the manifest of the resource in `resource_dir`.
+pub fn read_manifest(resource_dir: &Path) -> Result<ResourceManifest, ManifestError> {
+    let (path, kind) = find_manifest(resource_dir).ok_or(ManifestError::NotFound)?;
+    let source = fs::read_to_string(path).map_err(ManifestError::Io)?;
+    let mut manifest = ResourceManifest::parse(&source);
+    manifest.kind = Some(kind);
+    Ok(manifest)
+}
+
+/// Reads the manifest of every resource found by `detect_resources`, mapping resource names to
+/// their manifests. Resources whose manifest can't be read are left out.
+pub fn read_manifests(resources: &HashMap<String, String>) -> HashMap<String, ResourceManifest> {
+    resources
+        .iter()
+        .filter_map(|(name, path)| {
+            read_manifest(Path::new(path))
+                .ok()
+                .map(|manifest| (name.clone(), manifest))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn parses_manifest_directives() {
+        let manifest = ResourceManifest::parse(
+            r#"fx_version 'cerulean'
+games { 'gta5', "rdr3" }
+--[[ dependency 'commented'
+   server_script 'commented.lua' ]]
+-- dependency 'also-commented'
+--[==[ a ]] still a comment ]==]
+dependency 'oxmysql'
+dependencies {
+    '/server:5181',
+    'es_extended', -- trailing comment
+}
+provide 'mysql-async'
+lua54 'yes'
+client_scripts { 'client/*.lua', [[client/long.lua]] }
+server_script([==[server/]]main.lua]==])
+data_file 'DLC_ITYP_REQUEST' 'stream/props.ytyp'
+description [[A
+long description]]
+ui_page('html/index.html')
+"#,
+        );
+        assert_eq!(manifest.fx_version.as_deref(), Some("cerulean"));
+        assert_eq!(manifest.games, vec!["gta5", "rdr3"]);
+        assert_eq!(
+            manifest.dependencies,
+            vec!["oxmysql", "/server:5181", "es_extended"]
+        );
+        assert_eq!(
+            manifest.resource_dependencies(),
+            vec!["oxmysql", "es_extended"]
+        );
+        assert_eq!(manifest.provides, vec!["mysql-async"]);
+        assert!(manifest.lua54);
+        assert_eq!(
+            manifest.client_scripts,
+            vec!["client/*.lua", "client/long.lua"]
+        );
+        assert_eq!(manifest.server_scripts, vec!["server/]]main.lua"]);
+        assert_eq!(
+            manifest.metadata["data_file"],
+            vec!["DLC_ITYP_REQUEST", "stream/props.ytyp"]
+        );
+        assert_eq!(
+            manifest.metadata["description"],
+            vec!["A\nlong description"]
+        );
+        assert_eq!(manifest.ui_page.as_deref(), Some("html/index.html"));
+        assert_eq!(manifest.kind, None);
+
+        assert!(!ResourceManifest::parse("lua54 'no'").lua54);
+    }
+
+    #[test]
+    fn reads_legacy_manifests() {
+        let dir = temp_dir("manifest");
+        assert!(matches!(read_manifest(&dir), Err(ManifestError::NotFound)));
+
+        fs::write(
+            dir.join(LEGACY_MANIFEST),
+            "resource_manifest_version '44febabe-d386-4d18-afbe-5e627f4af937'\n\
+             dependency \"mapmanager\"\nclient_script 'client.lua'\n",
+        )
+        .unwrap();
+        let manifest = read_manifest(&dir).unwrap();
+        assert_eq!(manifest.kind, Some(ManifestKind::Legacy));
+        assert_eq!(manifest.dependencies, vec!["mapmanager"]);
+        assert_eq!(manifest.client_scripts, vec!["client.lua"]);
+
+        // A modern manifest is used over a legacy one
+        fs::write(dir.join(FXMANIFEST), "fx_version 'cerulean'\n").unwrap();
+        let manifest = read_manifest(&dir).unwrap();
+        assert_eq!(manifest.kind, Some(ManifestKind::FxManifest));
+        assert!(manifest.dependencies.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,232 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::artifacts::{Artifact, ArtifactListing, ArtifactSource, HtmlListing, ListingCache};
+use crate::install::{self, InstallError};
+use crate::integrity;
+
+/// An artifact copied into a mirror.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MirroredArtifact {
+    /// The artifact number of the build.
+    pub num: u16,
+    /// The hash of the build.
+    pub hash: String,
+    /// The path the archive was written to.
+    pub path: String,
+    /// The SHA-256 checksum of the archive.
+    pub sha256: String,
+    /// Whether the archive was already in the mirror, so wasn't downloaded again.
+    pub existing: bool,
+    /// Why the download failed from each URL tried before the one it came from.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// Copies an artifact's archive into the mirror in `dir`, laid out like the artifact server:
+/// `<dir>/<num>-<hash>/<archive>`, with its checksum beside it in `<archive>.sha256`. If the
+/// archive is already there and matches its checksum, it isn't downloaded again. The download
+/// is checked against the checksum the artifact server publishes, if it publishes one.
+/// `mirrors` and `progress` are passed to [`install::download`], and `mirrors` and `cache` to
+/// [`integrity::published_checksum`].
+pub fn mirror_artifact(
+    artifact: &Artifact,
+    mirrors: &[String],
+    cache: Option<&ListingCache>,
+    dir: &Path,
+    progress: &mut dyn FnMut(u64, Option<u64>),
+) -> Result<MirroredArtifact, InstallError> {
+    if artifact.broken {
+        return Err(InstallError::Broken(artifact.num));
+    }
+    let folder = dir.join(format!("{}-{}", artifact.num, artifact.hash));
+    let path = folder.join(&artifact.archive);
+    let checksum_path = folder.join(format!("{}.sha256", artifact.archive));
+    let mirrored = |sha256: String, warnings: Vec<String>, existing| MirroredArtifact {
+        num: artifact.num,
+        hash: artifact.hash.clone(),
+        path: path.to_string_lossy().into_owned(),
+        sha256,
+        existing,
+        warnings,
+    };
+
+    if let Some(recorded) = fs::read_to_string(&checksum_path)
+        .ok()
+        .and_then(|text| integrity::parse_checksum(&text))
+    {
+        if integrity::sha256_file(&path).ok().as_ref() == Some(&recorded) {
+            return Ok(mirrored(recorded, Vec::new(), true));
+        }
+    }
+
+    fs::create_dir_all(&folder)?;
+    let part = folder.join(format!(".{}.part", artifact.archive));
+    let result = (|| {
+        let warnings = install::download(artifact, mirrors, &part, progress)?;
+        let actual = integrity::sha256_file(&part)?;
+        if let Some(expected) = integrity::published_checksum(artifact, mirrors, cache) {
+            if expected != actual {
+                return Err(InstallError::ChecksumMismatch { expected, actual });
+            }
+        }
+        fs::rename(&part, &path)?;
+        fs::write(
+            &checksum_path,
+            format!("{}  {}\n", actual, artifact.archive),
+        )?;
+        Ok((actual, warnings))
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&part);
+    }
+    let (sha256, warnings) = result?;
+    Ok(mirrored(sha256, warnings, false))
+}
+
+/// Writes `index.html` for the mirror in `dir`, listing every artifact in it in the same form
+/// as the artifact server, so it can be used as an artifact server itself. If `recommended` or
+/// `optional` aren't given (or aren't in the mirror), the builds named by the existing index
+/// are kept. The listing written is returned.
+pub fn write_index(
+    dir: &Path,
+    recommended: Option<u16>,
+    optional: Option<u16>,
+) -> io::Result<ArtifactListing> {
+    let folder_re = Regex::new(r"^(\d+)-([\da-f]+)$").unwrap();
+    let mut artifacts = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let capture = match folder_re.captures(&name) {
+            Some(capture) if entry.path().is_dir() => capture,
+            _ => continue,
+        };
+        let num = match capture[1].parse::<u16>() {
+            Ok(num) => num,
+            Err(_) => continue,
+        };
+        for archive in ["fx.tar.xz", "server.zip"] {
+            if entry.path().join(archive).is_file() {
+                artifacts.push(Artifact {
+                    url: format!("./{}/", name),
+                    num,
+                    hash: capture[2].to_string(),
+                    archive: archive.to_string(),
+                    broken: false,
+                });
+            }
+        }
+    }
+    artifacts.sort_by(|a, b| b.cmp(a));
+
+    let previous = fs::read_to_string(dir.join("index.html"))
+        .ok()
+        .and_then(|body| HtmlListing.parse("./", &body))
+        .unwrap_or_default();
+    let pick = |given: Option<u16>, previous: Option<u16>| {
+        given
+            .filter(|num| artifacts.iter().any(|a| a.num == *num))
+            .or(previous.filter(|num| artifacts.iter().any(|a| a.num == *num)))
+    };
+    let listing = ArtifactListing {
+        recommended: pick(recommended, previous.recommended),
+        optional: pick(optional, previous.optional),
+        artifacts,
+        ..Default::default()
+    };
+
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n    <meta charset=\"utf-8\">\n    \
+         <title>FiveM server artifacts (mirror)</title>\n</head>\n<body>\n",
+    );
+    let button = |num: Option<u16>, label: &str| {
+        num.and_then(|num| listing.artifacts.iter().find(|a| a.num == num))
+            .map(|a| {
+                format!(
+                    "    <a href=\"{}{}\">LATEST {} ({})</a>\n",
+                    a.url, a.archive, label, a.num
+                )
+            })
+            .unwrap_or_default()
+    };
+    html.push_str(&button(listing.recommended, "RECOMMENDED"));
+    html.push_str(&button(listing.optional, "OPTIONAL"));
+    html.push_str("    <ul>\n");
+    for artifact in &listing.artifacts {
+        html.push_str(&format!(
+            "        <li><a href=\"{}{}\">{}-{}</a></li>\n",
+            artifact.url, artifact.archive, artifact.num, artifact.hash
+        ));
+    }
+    html.push_str("    </ul>\n</body>\n</html>\n");
+    fs::write(dir.join("index.html"), html)?;
+    Ok(listing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifacts::{ArtifactChannel, ArtifactServer};
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn mirrors_can_be_used_as_artifact_servers() {
+        let upstream = temp_dir("mirror-upstream");
+        let mirror = temp_dir("mirror");
+        for (num, hash) in [(5848, "4f71128e"), (6116, "7a6b5bd8")] {
+            let folder = upstream.join(format!("{}-{}", num, hash));
+            fs::create_dir_all(&folder).unwrap();
+            fs::write(folder.join("server.zip"), format!("build {}", num)).unwrap();
+        }
+        write_index(&upstream, Some(5848), None).unwrap();
+
+        let upstream_url = format!("file://{}/", upstream.display());
+        let mut server = ArtifactServer::new(&upstream_url);
+        let recommended = server.channel(ArtifactChannel::Recommended).unwrap();
+        let mirrored = mirror_artifact(&recommended, &[], None, &mirror, &mut |_, _| ()).unwrap();
+        assert!(!mirrored.existing);
+        assert_eq!(
+            fs::read_to_string(mirror.join("5848-4f71128e/server.zip")).unwrap(),
+            "build 5848"
+        );
+        assert!(
+            mirror_artifact(&recommended, &[], None, &mirror, &mut |_, _| ())
+                .unwrap()
+                .existing
+        );
+        write_index(&mirror, Some(5848), Some(6116)).unwrap();
+
+        // The mirror's own index is read, falling back from an artifact server which is down
+        let mirror_url = format!("file://{}/", mirror.display());
+        let mut server = ArtifactServer::new("file:///nonexistent/").with_mirror(&mirror_url);
+        assert_eq!(server.get_latest_version_num(), 5848);
+        assert_eq!(server.url(), mirror_url);
+        let warnings = server.take_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("file:///nonexistent/: "));
+        assert!(server.take_warnings().is_empty());
+        assert_eq!(server.channel_num(ArtifactChannel::Optional), None);
+        let artifact = server.get_artifact(5848).unwrap();
+        assert_eq!(
+            integrity::published_checksum(&artifact, &[], None).as_ref(),
+            Some(&mirrored.sha256)
+        );
+
+        // Downloads fall back to the same build on a mirror
+        fs::remove_dir_all(&upstream).unwrap();
+        let part = mirror.join("download.part");
+        let warnings =
+            install::download(&recommended, &[mirror_url], &part, &mut |_, _| ()).unwrap();
+        assert_eq!(fs::read_to_string(&part).unwrap(), "build 5848");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with(&recommended.download_url()));
+        assert!(install::download(&recommended, &[], &part, &mut |_, _| ()).is_err());
+
+        fs::remove_dir_all(&mirror).unwrap();
+    }
+}
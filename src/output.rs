@@ -0,0 +1,65 @@
+use clap::ArgEnum;
+use fivem_utility::config::edit::ConfigTree;
+use fivem_utility::secrets::{self, Redactor};
+use serde::Serialize;
+
+/// The format to print the results of a command in.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable text, using colors where the terminal supports them.
+    Table,
+    /// JSON, for scripts and dashboards.
+    Json,
+    /// YAML, for scripts and dashboards.
+    Yaml,
+}
+
+/// Prints the results of a command in the chosen format, hiding secrets if `--redact` is given.
+#[derive(Clone, Debug)]
+pub struct Printer {
+    /// The format to print results in.
+    pub format: OutputFormat,
+    /// The secrets to hide from everything printed, if they should be hidden.
+    pub redactor: Option<Redactor>,
+}
+
+impl Printer {
+    /// Whether the format is meant for machines rather than people.
+    pub fn is_structured(&self) -> bool {
+        self.format != OutputFormat::Table
+    }
+
+    /// Prints a value in a structured format. Nothing is printed for `Table`, as each command
+    /// prints its own human readable output.
+    pub fn print<T: Serialize>(&self, value: &T) {
+        if !self.is_structured() {
+            return;
+        }
+        let mut value = serde_json::to_value(value).unwrap();
+        if let Some(redactor) = &self.redactor {
+            redactor.redact_json(&mut value);
+        }
+        match self.format {
+            OutputFormat::Table => (),
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&value).unwrap()),
+            OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&value).unwrap()),
+        }
+    }
+
+    /// Hides the secrets found in the configs a command reads, in place of those found in the
+    /// main config. Does nothing if secrets aren't being hidden.
+    pub fn redact_configs(&mut self, trees: &[ConfigTree]) {
+        if let Some(redactor) = &mut self.redactor {
+            let found: Vec<secrets::Secret> = trees.iter().flat_map(secrets::scan).collect();
+            *redactor = Redactor::new(&found);
+        }
+    }
+
+    /// Hides any secrets in text printed in the `Table` format.
+    pub fn redact(&self, text: &str) -> String {
+        match &self.redactor {
+            Some(redactor) => redactor.redact(text),
+            None => text.to_string(),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::config;
+use crate::dependencies;
+use crate::manifest;
+
+/// The result of scanning a resources folder.
+#[derive(Clone, Debug, Default)]
+pub struct ResourceScan {
+    /// The resources found, mapping their name to their path.
+    pub resources: HashMap<String, String>,
+    /// Directories found which aren't categories (`[name]`) and have no manifest, so the
+    /// server will not treat them as resources, mapping their name to their path.
+    pub not_resources: HashMap<String, String>,
+}
+
+/// Adds the path an error happened at to its message.
+fn at_path(path: &Path, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+}
+
+/// A function to detect resources within a resources folder.
+pub fn detect_resources(resource_dir: &str) -> io::Result<HashMap<String, String>> {
+    Ok(scan_resources(resource_dir)?.resources)
+}
+
+/// A function to scan a resources folder, finding resources and any directories which look
+/// like they should be resources but have no manifest. An error is returned if the folder, or
+/// anything in it (such as a broken symlink), can't be read.
+pub fn scan_resources(resource_dir: &str) -> io::Result<ResourceScan> {
+    let paths = fs::read_dir(resource_dir).map_err(|e| at_path(Path::new(resource_dir), e))?;
+    let mut scan = ResourceScan::default();
+
+    for path in paths {
+        let entry = path?;
+        // Follow symlinks to directories
+        let meta = fs::metadata(entry.path()).map_err(|e| at_path(&entry.path(), e))?;
+        if meta.is_dir() {
+            let non_unicode = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: path isn't valid unicode", entry.path().display()),
+                )
+            };
+            let name = entry.file_name().into_string().map_err(|_| non_unicode())?;
+            let file_path = entry.path().to_str().ok_or_else(non_unicode)?.to_owned();
+            if name.starts_with('[') && name.ends_with(']') {
+                // recurse downwards
+                let sub_scan = scan_resources(&file_path)?;
+                scan.resources.extend(sub_scan.resources);
+                scan.not_resources.extend(sub_scan.not_resources);
+            } else if manifest::find_manifest(&entry.path()).is_some() {
+                scan.resources.insert(name, file_path);
+            } else if entry.metadata()?.is_dir() {
+                scan.not_resources.insert(name, file_path);
+            } else {
+                // Symlinks to directories which aren't resources are treated like categories
+                let sub_scan = scan_resources(&file_path)?;
+                scan.resources.extend(sub_scan.resources);
+                scan.not_resources.extend(sub_scan.not_resources);
+            }
+        }
+    }
+
+    Ok(scan)
+}
+
+/// A resource (or directory) and the path it was found at.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FoundResource {
+    /// The name of the resource.
+    pub name: String,
+    /// The path the resource was found at.
+    pub path: String,
+}
+
+/// The result of comparing the resources started by the config with the resources folder.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ResourceUsageReport {
+    /// Resources started by the config and found, in the order they are started.
+    pub found: Vec<FoundResource>,
+    /// Resources started by the config which couldn't be found.
+    pub missing: Vec<String>,
+    /// Directories started by the config which have no manifest, so aren't resources.
+    pub not_resources: Vec<FoundResource>,
+    /// Resources found which are never started by the config.
+    pub extra: Vec<FoundResource>,
+    /// Directories found which have no manifest and are never started by the config.
+    pub ignored: Vec<FoundResource>,
+    /// Problems with the order resources are started in.
+    pub dependencies: dependencies::DependencyReport,
+}
+
+/// Compares the resources started by the config with those found in the resources folder.
+pub fn resource_usage(
+    cfg: &config::FivemConfig,
+    resources_dir: &str,
+) -> io::Result<ResourceUsageReport> {
+    let scan = scan_resources(resources_dir)?;
+    let manifests = manifest::read_manifests(&scan.resources);
+    let mut resources = scan.resources;
+    let mut not_resources = scan.not_resources;
+    let mut report = ResourceUsageReport {
+        dependencies: dependencies::check_start_order(&cfg.resources, &manifests),
+        ..Default::default()
+    };
+
+    for res in &cfg.resources {
+        let name = res.name.clone();
+        if let Some(path) = resources.remove(&name) {
+            report.found.push(FoundResource { name, path });
+        } else if let Some(path) = not_resources.remove(&name) {
+            report.not_resources.push(FoundResource { name, path });
+        } else {
+            report.missing.push(name);
+        }
+    }
+    let sorted = |map: HashMap<String, String>| {
+        let mut list: Vec<FoundResource> = map
+            .into_iter()
+            .map(|(name, path)| FoundResource { name, path })
+            .collect();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        list
+    };
+    report.extra = sorted(resources);
+    report.ignored = sorted(not_resources);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn unreadable_folders_are_errors() {
+        let dir = temp_dir("resources");
+        fs::create_dir_all(dir.join("[maps]").join("map")).unwrap();
+        fs::write(dir.join("[maps]").join("map").join("fxmanifest.lua"), "").unwrap();
+        fs::create_dir_all(dir.join("notes")).unwrap();
+
+        let scan = scan_resources(dir.to_str().unwrap()).unwrap();
+        assert_eq!(scan.resources.keys().collect::<Vec<_>>(), vec!["map"]);
+        assert_eq!(scan.not_resources.keys().collect::<Vec<_>>(), vec!["notes"]);
+
+        assert!(detect_resources(dir.join("missing").to_str().unwrap()).is_err());
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("nowhere"), dir.join("broken")).unwrap();
+            let e = scan_resources(dir.to_str().unwrap()).unwrap_err();
+            assert!(e.to_string().contains("broken"));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
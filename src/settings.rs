@@ -0,0 +1,190 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::artifacts::Platform;
+
+/// The default file name of the settings file.
+pub const SETTINGS_FILE: &str = "fivem-utility.json";
+
+/// The environment variable which can list artifact servers to use, separated by commas.
+/// `{platform}` in each URL stands for `linux` or `windows`.
+pub const ARTIFACT_URL_ENV: &str = "FIVEM_ARTIFACT_URL";
+
+/// The placeholder in an artifact server URL which stands for the platform builds are for.
+pub const PLATFORM_PLACEHOLDER: &str = "{platform}";
+
+/// The artifact servers to use in place of the upstream one, as given in the settings file.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum ArtifactUrls {
+    /// The same servers for every platform, with `{platform}` standing for `linux` or
+    /// `windows`.
+    All(Vec<String>),
+    /// Separate servers for each platform.
+    PerPlatform {
+        /// The servers for Linux builds.
+        #[serde(default)]
+        linux: Vec<String>,
+        /// The servers for Windows builds.
+        #[serde(default)]
+        windows: Vec<String>,
+    },
+}
+
+impl Default for ArtifactUrls {
+    fn default() -> Self {
+        ArtifactUrls::All(Vec::new())
+    }
+}
+
+impl ArtifactUrls {
+    /// The servers for a platform, with `{platform}` filled in.
+    pub fn for_platform(&self, platform: Platform) -> Vec<String> {
+        let urls = match (self, platform) {
+            (ArtifactUrls::All(urls), _) => urls,
+            (ArtifactUrls::PerPlatform { linux, .. }, Platform::Linux) => linux,
+            (ArtifactUrls::PerPlatform { windows, .. }, Platform::Windows) => windows,
+        };
+        for_platform(urls, platform)
+    }
+}
+
+/// The environment variable listing the artifact servers for one platform, which is used over
+/// [`ARTIFACT_URL_ENV`]: `FIVEM_ARTIFACT_URL_LINUX` or `FIVEM_ARTIFACT_URL_WINDOWS`.
+pub fn artifact_url_env(platform: Platform) -> String {
+    format!(
+        "{}_{}",
+        ARTIFACT_URL_ENV,
+        platform.to_string().to_uppercase()
+    )
+}
+
+/// Fills in `{platform}` in each URL.
+fn for_platform(urls: &[String], platform: Platform) -> Vec<String> {
+    urls.iter()
+        .map(|url| url.replace(PLATFORM_PLACEHOLDER, &platform.to_string()))
+        .collect()
+}
+
+/// Settings for the tool, read from `fivem-utility.json`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// The artifact servers to use in place of the upstream one, tried in order. These are
+    /// usually internal mirrors, given either for each platform (`{"linux": [...], "windows":
+    /// [...]}`) or as one list using `{platform}`.
+    pub artifact_urls: ArtifactUrls,
+    /// Where the summary of a build's changes is read from, with `{hash}` standing for the
+    /// build's commit hash. See [`ChangelogSource`](crate::artifacts::ChangelogSource).
+    pub changelog_url: Option<String>,
+}
+
+/// The error returned when the settings file couldn't be read.
+#[derive(Debug)]
+pub enum SettingsError {
+    /// The settings file couldn't be read.
+    Io(io::Error),
+    /// The settings file isn't valid.
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::Io(e) => write!(f, "{}", e),
+            SettingsError::Parse(e) => write!(f, "invalid settings file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl Settings {
+    /// Reads the settings file. If it doesn't exist, the default settings are used.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, SettingsError> {
+        match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).map_err(SettingsError::Parse),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(SettingsError::Io(e)),
+        }
+    }
+}
+
+/// Works out which artifact servers to use for a platform, in the order they should be tried.
+/// URLs given on the command line take priority, then those in the environment variable for the
+/// platform (see [`artifact_url_env`]), then `FIVEM_ARTIFACT_URL`, then those in the settings
+/// file, and then the upstream artifact server. `{platform}` in any URL is filled in.
+pub fn artifact_urls(platform: Platform, given: &[String], settings: &Settings) -> Vec<String> {
+    let from_env = |name: &str| -> Vec<String> {
+        env::var(name)
+            .unwrap_or_default()
+            .split(',')
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty())
+            .collect()
+    };
+    [
+        given.to_vec(),
+        from_env(&artifact_url_env(platform)),
+        from_env(ARTIFACT_URL_ENV),
+        settings.artifact_urls.for_platform(platform),
+    ]
+    .into_iter()
+    .find(|urls| !urls.is_empty())
+    .map(|urls| for_platform(&urls, platform))
+    .unwrap_or_else(|| vec![platform.url().to_string()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn artifact_servers_are_chosen_for_each_platform() {
+        let settings: Settings = serde_json::from_str(
+            r#"{"artifact_urls": {"linux": ["https://mirror/linux/"], "windows": ["https://mirror/win/"]}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            settings.artifact_urls.for_platform(Platform::Linux),
+            vec!["https://mirror/linux/"]
+        );
+        assert_eq!(
+            settings.artifact_urls.for_platform(Platform::Windows),
+            vec!["https://mirror/win/"]
+        );
+
+        let settings: Settings =
+            serde_json::from_str(r#"{"artifact_urls": ["https://mirror/{platform}/"]}"#).unwrap();
+        assert_eq!(
+            settings.artifact_urls.for_platform(Platform::Windows),
+            vec!["https://mirror/windows/"]
+        );
+        let given = vec!["file:///srv/mirror/{platform}/".to_string()];
+        assert_eq!(
+            artifact_urls(Platform::Linux, &given, &settings),
+            vec!["file:///srv/mirror/linux/"]
+        );
+        assert_eq!(
+            artifact_urls(Platform::Windows, &given, &settings),
+            vec!["file:///srv/mirror/windows/"]
+        );
+
+        // Only one platform's servers are given, so the other uses the upstream server
+        let settings: Settings =
+            serde_json::from_str(r#"{"artifact_urls": {"linux": ["https://mirror/"]}}"#).unwrap();
+        assert!(settings
+            .artifact_urls
+            .for_platform(Platform::Windows)
+            .is_empty());
+        assert_eq!(
+            artifact_url_env(Platform::Windows),
+            "FIVEM_ARTIFACT_URL_WINDOWS"
+        );
+    }
+}
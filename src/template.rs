@@ -0,0 +1,439 @@
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use regex::{Captures, Regex};
+
+use crate::config::document::{Document, Line};
+use crate::config::{setting, ConfigError, Diagnostic, Severity};
+
+/// The name of the variable holding the environment being rendered for, such as `prod`.
+pub const ENV_VAR: &str = "ENV";
+
+/// A `${VAR}` or `${VAR:-default}` placeholder.
+const PLACEHOLDER: &str = r"\$\{(\w+)(:-([^}]*))?\}";
+
+/// The patterns templates are rendered with, compiled once as they are used on every line.
+struct Patterns {
+    placeholder: Regex,
+    comparison: Regex,
+    variable: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| Patterns {
+        placeholder: Regex::new(PLACEHOLDER).unwrap(),
+        comparison: Regex::new(r"^(\w+)\s*(==|!=)\s*(.*)$").unwrap(),
+        variable: Regex::new(r"^\w+$").unwrap(),
+    })
+}
+
+/// Replaces each `${VAR}` in some text with the value `vars` gives for it, or with the default
+/// in `${VAR:-default}` if it has none. The names of any variables without a value or a default
+/// are returned instead, in the order they are first used.
+pub fn substitute(
+    text: &str,
+    vars: &dyn Fn(&str) -> Option<String>,
+) -> Result<String, Vec<String>> {
+    let mut missing: Vec<String> = Vec::new();
+    let substituted = patterns()
+        .placeholder
+        .replace_all(text, |capture: &Captures| {
+            vars(&capture[1])
+                .or_else(|| capture.get(3).map(|default| default.as_str().to_string()))
+                .unwrap_or_else(|| {
+                    if !missing.iter().any(|m| m == &capture[1]) {
+                        missing.push(capture[1].to_string());
+                    }
+                    String::new()
+                })
+        });
+    if missing.is_empty() {
+        Ok(substituted.into_owned())
+    } else {
+        Err(missing)
+    }
+}
+
+/// The variables used in some text whose values contain a newline or a quote. These can't be
+/// written in a config, and could add lines to it.
+pub fn unsafe_vars(text: &str, vars: &dyn Fn(&str) -> Option<String>) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for capture in patterns().placeholder.captures_iter(text) {
+        let unsafe_value = vars(&capture[1]).is_some_and(|v| v.contains(['\r', '\n', '"']));
+        if unsafe_value && !names.iter().any(|n| n == &capture[1]) {
+            names.push(capture[1].to_string());
+        }
+    }
+    names
+}
+
+/// Whether the condition of an `#@if` holds. A condition is either a variable, which holds if
+/// it is set to anything but an empty string, `0`, `false`, `no` or `off`, or a comparison of a
+/// variable with a value (`VAR == value` or `VAR != value`).
+fn condition_holds(condition: &str, vars: &dyn Fn(&str) -> Option<String>) -> Option<bool> {
+    if let Some(capture) = patterns().comparison.captures(condition) {
+        let value = vars(&capture[1]).unwrap_or_default();
+        let expected = capture[3].trim().trim_matches('"');
+        return Some((value == expected) == (&capture[2] == "=="));
+    }
+    if patterns().variable.is_match(condition) {
+        let value = vars(condition).unwrap_or_default();
+        return Some(!["", "0", "false", "no", "off"].contains(&value.to_lowercase().as_str()));
+    }
+    None
+}
+
+/// Renders the text of a config template. `${VAR}` and `${VAR:-default}` are replaced with
+/// the values `vars` gives, and lines between `#@if <condition>`, `#@else` and `#@endif` are
+/// kept or dropped depending on the condition. As the directives are comments, templates which
+/// only use them are still valid configs. Comments are left as they are.
+///
+/// Values containing a newline or a quote are refused, as they could add lines to the config.
+/// Rendering does not stop at the first problem; every problem found is returned.
+pub fn render(
+    path: &str,
+    text: &str,
+    vars: &dyn Fn(&str) -> Option<String>,
+) -> Result<String, ConfigError> {
+    let mut diagnostics = Vec::new();
+    let mut report =
+        |line: usize, column: usize, directive: Option<&str>, snippet: &str, message| {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                message,
+                file: path.to_string(),
+                line,
+                column,
+                directive: directive.map(String::from),
+                snippet: Some(snippet.to_string()),
+            })
+        };
+
+    // Whether each open `#@if` is taking its branch, and the line it was opened on
+    let mut blocks: Vec<(bool, usize, String)> = Vec::new();
+    let mut rendered = String::new();
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        let number = i + 1;
+        let content = line.trim_end_matches(['\r', '\n']);
+        let trimmed = content.trim_start();
+        let column = content.len() - trimmed.len() + 1;
+        if let Some(directive) = trimmed.strip_prefix("#@") {
+            let (name, condition) = directive
+                .split_once(char::is_whitespace)
+                .unwrap_or((directive, ""));
+            let condition = condition.trim();
+            match name {
+                "if" => {
+                    let holds = condition_holds(condition, vars).unwrap_or_else(|| {
+                        report(
+                            number,
+                            column,
+                            Some("#@if"),
+                            content,
+                            format!("`{}` isn't a valid condition", condition),
+                        );
+                        false
+                    });
+                    blocks.push((holds, number, content.to_string()));
+                }
+                "else" => match blocks.last_mut() {
+                    Some(block) => block.0 = !block.0,
+                    None => report(
+                        number,
+                        column,
+                        Some("#@else"),
+                        content,
+                        "`#@else` without an `#@if`".to_string(),
+                    ),
+                },
+                "endif" => {
+                    if blocks.pop().is_none() {
+                        report(
+                            number,
+                            column,
+                            Some("#@endif"),
+                            content,
+                            "`#@endif` without an `#@if`".to_string(),
+                        );
+                    }
+                }
+                _ => report(
+                    number,
+                    column,
+                    None,
+                    content,
+                    format!("`#@{}` isn't a template directive", name),
+                ),
+            }
+            continue;
+        }
+        if !blocks.iter().all(|block| block.0) {
+            continue;
+        }
+        if trimmed.starts_with('#') || trimmed.starts_with("//") {
+            rendered.push_str(line);
+            continue;
+        }
+        for name in unsafe_vars(line, vars) {
+            let column = content.find(&format!("${{{}", name)).unwrap_or(0) + 1;
+            report(
+                number,
+                column,
+                None,
+                content,
+                format!(
+                    "`{}` contains a newline or a quote, which can't be written in a config",
+                    name
+                ),
+            );
+        }
+        match substitute(line, vars) {
+            Ok(line) => rendered.push_str(&line),
+            Err(missing) => {
+                for name in missing {
+                    let column = content.find(&format!("${{{}", name)).unwrap_or(0) + 1;
+                    report(
+                        number,
+                        column,
+                        None,
+                        content,
+                        format!("`{}` isn't set and has no default", name),
+                    );
+                }
+            }
+        }
+    }
+    for (_, number, content) in blocks {
+        report(
+            number,
+            1,
+            Some("#@if"),
+            &content,
+            "`#@if` is never closed with `#@endif`".to_string(),
+        );
+    }
+
+    if diagnostics.is_empty() {
+        Ok(rendered)
+    } else {
+        Err(ConfigError { diagnostics })
+    }
+}
+
+/// The path of a file beside a config, named after it: `server.<name>.cfg` for `server.cfg`.
+fn sibling_path(config: &str, name: &str) -> String {
+    let path = Path::new(config);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    path.with_file_name(format!("{}.{}{}", stem, name, extension))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The path of the base template a config is rendered from: `server.base.cfg` for `server.cfg`.
+pub fn base_path(config: &str) -> String {
+    sibling_path(config, "base")
+}
+
+/// The path of the overlay for an environment: `server.prod.cfg` for `server.cfg` in `prod`.
+pub fn overlay_path(config: &str, env: &str) -> String {
+    sibling_path(config, env)
+}
+
+/// Applies an overlay to a config. Each convar the overlay sets replaces the last line setting
+/// it in the base, and any earlier lines setting it are dropped, so the rendered config sets it
+/// once. Every other line of the overlay is added to the end of the base.
+pub fn apply_overlay(base: &mut Document, overlay: &Document) {
+    for line in &overlay.lines {
+        let name = line
+            .item
+            .statement()
+            .and_then(setting)
+            .map(|(name, _)| name.to_lowercase());
+        let existing: Vec<usize> = match name {
+            Some(name) => (0..base.lines.len())
+                .filter(|&l| {
+                    base.lines[l]
+                        .item
+                        .statement()
+                        .and_then(setting)
+                        .is_some_and(|(n, _)| n.to_lowercase() == name)
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        match existing.split_last() {
+            Some((&last, earlier)) => {
+                base.lines[last].item = line.item.clone();
+                for &l in earlier.iter().rev() {
+                    base.lines.remove(l);
+                }
+            }
+            None => {
+                // The base's last line may not have had a newline
+                let newline = base.newline().to_string();
+                if let Some(last) = base.lines.last_mut() {
+                    if last.ending.is_empty() {
+                        last.ending = newline;
+                    }
+                }
+                base.lines.push(Line {
+                    item: line.item.clone(),
+                    ending: line.ending.clone(),
+                });
+            }
+        }
+    }
+}
+
+/// Reads and renders a template, reporting a file which can't be read as a diagnostic.
+fn render_file(path: &str, vars: &dyn Fn(&str) -> Option<String>) -> Result<Document, ConfigError> {
+    let text = fs::read_to_string(path).map_err(|e| ConfigError {
+        diagnostics: vec![Diagnostic {
+            severity: Severity::Error,
+            message: format!("failed to read `{}`: {}", path, e),
+            file: path.to_string(),
+            line: 0,
+            column: 0,
+            directive: None,
+            snippet: None,
+        }],
+    })?;
+    Ok(Document::parse(path, &render(path, &text, vars)?))
+}
+
+/// Renders the config at `config` for an environment. The template is `server.base.cfg` beside
+/// `server.cfg` if there is one, otherwise `server.cfg` itself. If `env` is given, it is
+/// available to the template as `${ENV}`, and `server.<env>.cfg` is applied over the template
+/// if it exists. The rendered document has the path of the config.
+pub fn render_config(
+    config: &str,
+    env: Option<&str>,
+    vars: &dyn Fn(&str) -> Option<String>,
+) -> Result<Document, ConfigError> {
+    let vars = |name: &str| match (name, env) {
+        (ENV_VAR, Some(env)) => Some(env.to_string()),
+        _ => vars(name),
+    };
+    let base = base_path(config);
+    let template = if Path::new(&base).is_file() {
+        &base
+    } else {
+        config
+    };
+    let mut doc = render_file(template, &vars)?;
+    if let Some(env) = env {
+        let overlay = overlay_path(config, env);
+        if Path::new(&overlay).is_file() {
+            apply_overlay(&mut doc, &render_file(&overlay, &vars)?);
+        }
+    }
+    doc.path = config.to_string();
+    Ok(doc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(name: &str) -> Option<String> {
+        match name {
+            "HOSTNAME" => Some("Test Server".to_string()),
+            "DEBUG" => Some("0".to_string()),
+            "ENV" => Some("prod".to_string()),
+            "INJECTED" => Some("x\nadd_ace builtin.everyone command allow".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn renders_variables_and_conditions() {
+        let text = "sv_hostname \"${HOSTNAME}\"\n\
+                    sv_maxclients ${MAX_CLIENTS:-32}\n\
+                    #@if ENV == prod\n\
+                    sv_scriptHookAllowed 0\n\
+                    #@if DEBUG\n\
+                    ensure debugger\n\
+                    #@endif\n\
+                    #@else\n\
+                    sv_scriptHookAllowed 1\n\
+                    #@endif\n\
+                    # ${NOT_SUBSTITUTED}\n";
+        assert_eq!(
+            render("server.cfg", text, &vars).unwrap(),
+            "sv_hostname \"Test Server\"\n\
+             sv_maxclients 32\n\
+             sv_scriptHookAllowed 0\n\
+             # ${NOT_SUBSTITUTED}\n"
+        );
+
+        let e = render(
+            "server.cfg",
+            "set key ${KEY}\nset a ${INJECTED}\n#@else\n#@if\n#@if DEBUG\n",
+            &vars,
+        )
+        .unwrap_err();
+        let found: Vec<(usize, usize, &str)> = e
+            .diagnostics
+            .iter()
+            .map(|d| (d.line, d.column, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (1, 9, "`KEY` isn't set and has no default"),
+                (
+                    2,
+                    7,
+                    "`INJECTED` contains a newline or a quote, which can't be written in a config"
+                ),
+                (3, 1, "`#@else` without an `#@if`"),
+                (4, 1, "`` isn't a valid condition"),
+                (4, 1, "`#@if` is never closed with `#@endif`"),
+                (5, 1, "`#@if` is never closed with `#@endif`"),
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_overlays() {
+        assert_eq!(base_path("cfg/server.cfg"), "cfg/server.base.cfg");
+        assert_eq!(
+            overlay_path("cfg/server.cfg", "prod"),
+            "cfg/server.prod.cfg"
+        );
+        let mut base =
+            Document::parse("server.cfg", "sv_maxclients 8\nset onesync on\nensure chat");
+        let overlay = Document::parse(
+            "server.prod.cfg",
+            "# Production\nsv_maxclients 48\nensure es_extended\n",
+        );
+        apply_overlay(&mut base, &overlay);
+        assert_eq!(
+            base.to_string(),
+            "sv_maxclients 48\nset onesync on\nensure chat\n# Production\nensure es_extended\n"
+        );
+    }
+
+    #[test]
+    fn overlays_replace_every_definition() {
+        let mut base = Document::parse(
+            "server.cfg",
+            "set onesync off\nsv_maxclients 8\nensure chat\nSET OneSync legacy\n",
+        );
+        let overlay = Document::parse("server.prod.cfg", "set onesync on\n");
+        apply_overlay(&mut base, &overlay);
+        assert_eq!(
+            base.to_string(),
+            "sv_maxclients 8\nensure chat\nset onesync on\n"
+        );
+    }
+}